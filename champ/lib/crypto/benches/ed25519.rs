@@ -0,0 +1,43 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use crypto::signatures::ed25519::{
+    create_public_key, create_signature, generate_private_key, verify_signature, verify_signatures_batch,
+};
+
+// every block is signed by a different account, like during a sync
+fn signed_messages(count: usize) -> Vec<(Vec<u8>, [u8; 32], [u8; 64])> {
+    (0..count)
+        .map(|i| {
+            let private_key = generate_private_key().expect("should generate private key");
+            let public_key = create_public_key(&private_key).expect("should create public key");
+            let data = format!("block {}", i).into_bytes();
+            let signature = create_signature(&data, &private_key).expect("should create signature");
+            (data, public_key, signature)
+        })
+        .collect()
+}
+
+fn bench_verification(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ed25519 verification");
+    for count in [1, 16, 64, 256, 1024] {
+        let messages = signed_messages(count);
+        group.throughput(Throughput::Elements(count as u64));
+
+        group.bench_with_input(BenchmarkId::new("per block", count), &messages, |b, messages| {
+            b.iter(|| {
+                for (data, public_key, signature) in messages {
+                    verify_signature(data, public_key, signature).expect("should be valid");
+                }
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("batch", count), &messages, |b, messages| {
+            let items: Vec<(&[u8], &[u8], &[u8])> =
+                messages.iter().map(|(d, k, s)| (d.as_slice(), k.as_slice(), s.as_slice())).collect();
+            b.iter(|| verify_signatures_batch(&items))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_verification);
+criterion_main!(benches);
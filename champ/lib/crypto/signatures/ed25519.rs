@@ -1,4 +1,4 @@
-use ed25519_zebra::{SigningKey, VerificationKey};
+use ed25519_zebra::{batch, Signature, SigningKey, VerificationKey, VerificationKeyBytes};
 use rand::thread_rng;
 use thiserror::Error;
 
@@ -22,6 +22,42 @@ pub fn verify_signature(data: &[u8], public_key: &[u8], data_signature: &[u8]) -
     Ok(())
 }
 
+/// Verify the signatures of many messages at once
+///
+/// Items are `(data, public_key, signature)` tuples. Batch verification is considerably
+/// faster than verifying each signature on its own. If the batch contains an invalid signature,
+/// every signature is verified individually to find it.
+/// Returns the result for each item in the order they were passed in.
+pub fn verify_signatures_batch(items: &[(&[u8], &[u8], &[u8])]) -> Vec<Result<(), Ed25519Error>> {
+    let mut verifier = batch::Verifier::new();
+    let mut queued = vec![];
+    let mut results: Vec<Result<(), Ed25519Error>> = Vec::with_capacity(items.len());
+
+    for (index, (data, public_key, data_signature)) in items.iter().enumerate() {
+        let item = VerificationKeyBytes::try_from(*public_key).ok().and_then(|vk_bytes| {
+            let sig_bytes: [u8; 64] = (*data_signature).try_into().ok()?;
+            Some(batch::Item::from((vk_bytes, Signature::from(sig_bytes), data)))
+        });
+
+        match item {
+            Some(item) => {
+                verifier.queue(item.clone());
+                queued.push((index, item));
+                results.push(Ok(()));
+            }
+            None => results.push(Err(Ed25519Error::VerificationError)),
+        }
+    }
+
+    if verifier.verify(thread_rng()).is_err() {
+        for (index, item) in queued {
+            results[index] = item.verify_single().map_err(|_| Ed25519Error::VerificationError);
+        }
+    }
+
+    results
+}
+
 /// create signature from data
 pub fn create_signature(data: &[u8], private_key: &[u8]) -> Result<[u8; 64], Ed25519Error> {
     let signing_key = SigningKey::try_from(private_key).map_err(|_| Ed25519Error::VerificationError)?;
@@ -42,3 +78,31 @@ pub fn create_public_key(private_key: &[u8]) -> Result<[u8; 32], Ed25519Error> {
     let pk: [u8; 32] = VerificationKey::from(&signing_key).into();
     Ok(pk)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batch_verification() {
+        let private_key = generate_private_key().expect("should generate private key");
+        let public_key = create_public_key(&private_key).expect("should create public key");
+        let valid = create_signature(b"valid", &private_key).expect("should create signature");
+        let other = create_signature(b"other", &private_key).expect("should create signature");
+
+        let results: Vec<bool> = verify_signatures_batch(&[
+            (b"valid", &public_key, &valid),
+            (b"valid", &public_key, &other),
+            (b"valid", &public_key, b"too short"),
+            (b"other", &public_key, &other),
+        ])
+        .iter()
+        .map(|r| r.is_ok())
+        .collect();
+        assert_eq!(results, vec![true, false, false, true]);
+
+        let results = verify_signatures_batch(&[(b"valid", &public_key, &valid), (b"other", &public_key, &other)]);
+        assert!(results.iter().all(|r| r.is_ok()));
+        assert!(verify_signatures_batch(&[]).is_empty());
+    }
+}
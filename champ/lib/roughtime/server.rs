@@ -1,4 +1,8 @@
+use std::future::Future;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
 
 use hex;
 use roughenough::config::{MemoryConfig, ServerConfig, DEFAULT_BATCH_SIZE, DEFAULT_STATUS_INTERVAL};
@@ -13,16 +17,23 @@ impl RoughTime {
         RoughTime {}
     }
 
-    pub async fn polling_loop(config: Box<dyn ServerConfig>) {
+    /// Answers requests until `keep_running` is set to false, blocks the current thread
+    pub fn polling_loop(config: Box<dyn ServerConfig>, keep_running: Arc<AtomicBool>) {
         let mut server = Server::new(config);
         let mut events = MioEvents::with_capacity(1024);
 
-        loop {
+        while keep_running.load(Ordering::Relaxed) {
             server.process_events(&mut events);
         }
     }
 
-    pub async fn start(self, addr: SocketAddr, enabled: bool) -> Result<(), Box<dyn std::error::Error>> {
+    /// Runs the server on its own thread until `shutdown` resolves
+    pub async fn start(
+        self,
+        addr: SocketAddr,
+        enabled: bool,
+        shutdown: impl Future<Output = ()>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
         if !enabled {
             return Ok(());
         }
@@ -40,7 +51,16 @@ impl RoughTime {
         };
 
         info!("starting roughtime server at {}", addr);
-        Self::polling_loop(Box::from(config)).await;
+        // the server blocks while it waits for requests, so it can't run on the async runtime
+        let keep_running = Arc::new(AtomicBool::new(true));
+        let running = keep_running.clone();
+        thread::Builder::new()
+            .name("roughtime".to_string())
+            .spawn(move || Self::polling_loop(Box::from(config), running))?;
+
+        shutdown.await;
+        info!("stopping roughtime server");
+        keep_running.store(false, Ordering::Relaxed);
         Ok(())
     }
 }
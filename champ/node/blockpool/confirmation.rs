@@ -0,0 +1,39 @@
+use std::fmt;
+
+use pog_proto::api::SignedBlock;
+
+/// Final outcome of a block accepted by the blockpool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockStatus {
+    /// The block was committed to the database
+    Confirmed,
+    /// The block was valid but didn't get confirmed, or a parked block turned out to be invalid
+    Rejected,
+    /// The block was dropped before it could be confirmed, e.g. because its dependencies never arrived
+    Expired,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Confirm,
+    Reject,
+    /// Keep the block in the queue and ask again later
+    Wait,
+}
+
+/// Decides whether a queued block can be committed
+///
+/// Blocks only reach the queue after they are validated and all of their dependencies are committed.
+pub trait ConfirmationPolicy: fmt::Debug + Send + Sync {
+    fn decide(&mut self, block: &SignedBlock) -> Decision;
+}
+
+/// Confirms every valid block right away, used until blocks are confirmed by voting
+#[derive(Debug, Default)]
+pub struct Immediate;
+
+impl ConfirmationPolicy for Immediate {
+    fn decide(&mut self, _block: &SignedBlock) -> Decision {
+        Decision::Confirm
+    }
+}
@@ -0,0 +1,140 @@
+use pog_proto::api::{AccountID, BlockID, SignedBlock};
+use std::collections::{BTreeMap, HashMap};
+use std::time::Instant;
+
+use crate::consensus::vote::VoteResult;
+
+/// Blocks of an account competing for the same height and the prime delegates' votes for them
+#[derive(Debug)]
+pub struct Fork {
+    pub account: AccountID,
+    pub height: u64,
+    /// the competing block that is already committed, if any
+    pub committed: Option<BlockID>,
+    pub detected_at: Instant,
+    // sorted by block id, so every node iterates them in the same order
+    candidates: BTreeMap<BlockID, SignedBlock>,
+    // only the latest vote of each voter counts
+    votes: HashMap<AccountID, (BlockID, u32)>,
+}
+
+impl Fork {
+    pub fn new(account: AccountID, height: u64, committed: Option<BlockID>, detected_at: Instant) -> Self {
+        Self {
+            account,
+            height,
+            committed,
+            detected_at,
+            candidates: BTreeMap::new(),
+            votes: HashMap::new(),
+        }
+    }
+
+    pub fn add_candidate(&mut self, block_id: BlockID, block: SignedBlock) {
+        self.candidates.insert(block_id, block);
+    }
+
+    pub fn contains(&self, block_id: &BlockID) -> bool {
+        self.candidates.contains_key(block_id)
+    }
+
+    pub fn candidates(&self) -> impl Iterator<Item = (&BlockID, &SignedBlock)> {
+        self.candidates.iter()
+    }
+
+    /// Number of competing blocks that are not committed
+    pub fn pending_len(&self) -> usize {
+        self.candidates.keys().filter(|id| self.committed.as_ref() != Some(id)).count()
+    }
+
+    pub fn take_candidate(&mut self, block_id: &BlockID) -> Option<SignedBlock> {
+        self.candidates.remove(block_id)
+    }
+
+    pub fn into_candidates(self) -> impl Iterator<Item = (BlockID, SignedBlock)> {
+        self.candidates.into_iter()
+    }
+
+    /// Returns the competing blocks that are not committed
+    pub fn into_pending_blocks(self) -> Vec<SignedBlock> {
+        let committed = self.committed;
+        self.candidates.into_iter().filter(|(id, _)| Some(*id) != committed).map(|(_, block)| block).collect()
+    }
+
+    /// Adds a vote for one of the competing blocks, replacing the voter's earlier vote
+    pub fn add_vote(&mut self, voter: AccountID, block_id: BlockID, power: u32) {
+        self.votes.insert(voter, (block_id, power));
+    }
+
+    pub fn power(&self, block_id: &BlockID) -> u64 {
+        self.votes.values().filter(|(id, _)| id == block_id).map(|(_, power)| *power as u64).sum()
+    }
+
+    pub fn result(&self, block_id: &BlockID, total_power: u64) -> VoteResult {
+        VoteResult::new(self.power(block_id), total_power)
+    }
+
+    /// The competing block with the most voting power
+    ///
+    /// Ties are broken by the lowest block id, so every node that saw the same votes picks the same block.
+    pub fn leader(&self) -> Option<BlockID> {
+        // max_by_key returns the last maximum, so the ids are iterated from highest to lowest
+        self.candidates.keys().rev().max_by_key(|id| self.power(id)).copied()
+    }
+
+    /// Returns the winning block once it received votes of more than two thirds of the total voting power
+    pub fn winner(&self, total_power: u64) -> Option<BlockID> {
+        let leader = self.leader()?;
+        match self.result(&leader, total_power) {
+            VoteResult::Resolved {
+                ..
+            } => Some(leader),
+            VoteResult::Unresolved {
+                ..
+            } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fork() -> Fork {
+        let mut fork = Fork::new([0; 24], 1, Some([2; 32]), Instant::now());
+        fork.add_candidate([2; 32], SignedBlock::default());
+        fork.add_candidate([1; 32], SignedBlock::default());
+        fork
+    }
+
+    #[test]
+    fn test_fork_leader() {
+        let mut fork = fork();
+        assert_eq!(fork.pending_len(), 1);
+
+        // without votes the lowest block id leads
+        assert_eq!(fork.leader(), Some([1; 32]));
+
+        fork.add_vote([1; 24], [2; 32], 30);
+        assert_eq!(fork.leader(), Some([2; 32]));
+
+        // ties are broken by the block id
+        fork.add_vote([2; 24], [1; 32], 30);
+        assert_eq!(fork.leader(), Some([1; 32]));
+
+        // voters can change their vote
+        fork.add_vote([2; 24], [2; 32], 30);
+        assert_eq!(fork.power(&[1; 32]), 0);
+        assert_eq!(fork.power(&[2; 32]), 60);
+    }
+
+    #[test]
+    fn test_fork_winner() {
+        let mut fork = fork();
+        fork.add_vote([1; 24], [2; 32], 60);
+        assert_eq!(fork.winner(100), None);
+
+        fork.add_vote([2; 24], [2; 32], 7);
+        assert_eq!(fork.winner(100), Some([2; 32]));
+    }
+}
@@ -1,2 +1,6 @@
+mod confirmation;
+mod fork;
+mod parked;
 mod pool;
+pub use confirmation::*;
 pub use pool::*;
@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use pog_proto::api::{AccountID, BlockID, SignedBlock};
+
+use crate::validation::block::Dependency;
+
+#[derive(Debug)]
+pub struct ParkedBlock {
+    pub block: SignedBlock,
+    pub account: AccountID,
+    pub priority: u32,
+    pub dependencies: Vec<Dependency>,
+    pub parked_at: Instant,
+}
+
+/// Blocks that arrived before a block or transaction they depend on
+#[derive(Debug, Default)]
+pub struct ParkedBlocks {
+    blocks: HashMap<BlockID, ParkedBlock>,
+    // parked blocks indexed by the dependencies they are waiting for
+    waiting: HashMap<Dependency, Vec<BlockID>>,
+}
+
+impl ParkedBlocks {
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    pub fn contains(&self, block_id: &BlockID) -> bool {
+        self.blocks.contains_key(block_id)
+    }
+
+    /// Returns all parked blocks, oldest first
+    pub fn blocks(&self) -> Vec<&SignedBlock> {
+        let mut parked: Vec<(&BlockID, &ParkedBlock)> = self.blocks.iter().collect();
+        parked.sort_by_key(|(id, parked)| (parked.parked_at, **id));
+        parked.into_iter().map(|(_, parked)| &parked.block).collect()
+    }
+
+    pub fn count_by_account(&self, account: &AccountID) -> usize {
+        self.blocks.values().filter(|parked| &parked.account == account).count()
+    }
+
+    /// Returns the parked block with the lowest priority, the most recent one if there are several
+    pub fn lowest_priority(&self) -> Option<(BlockID, u32)> {
+        self.blocks
+            .iter()
+            .min_by_key(|(id, parked)| (parked.priority, std::cmp::Reverse(parked.parked_at), **id))
+            .map(|(id, parked)| (*id, parked.priority))
+    }
+
+    /// Parks a block until one of its dependencies arrives
+    pub fn park(&mut self, block_id: BlockID, parked: ParkedBlock) {
+        self.unpark(&block_id);
+
+        for dependency in &parked.dependencies {
+            self.waiting.entry(*dependency).or_default().push(block_id);
+        }
+        self.blocks.insert(block_id, parked);
+    }
+
+    /// Removes a block without waiting for its dependencies
+    pub fn remove(&mut self, block_id: &BlockID) -> bool {
+        self.unpark(block_id).is_some()
+    }
+
+    /// Removes and returns all blocks waiting for a dependency
+    pub fn release(&mut self, dependency: &Dependency) -> Vec<ParkedBlock> {
+        let block_ids = self.waiting.remove(dependency).unwrap_or_default();
+        block_ids.iter().filter_map(|id| self.unpark(id)).collect()
+    }
+
+    /// Removes all blocks that have been waiting for longer than `timeout` and returns their ids
+    pub fn expire(&mut self, now: Instant, timeout: Duration) -> Vec<BlockID> {
+        let expired: Vec<BlockID> = self
+            .blocks
+            .iter()
+            .filter(|(_, parked)| now.saturating_duration_since(parked.parked_at) > timeout)
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in &expired {
+            self.unpark(id);
+        }
+        expired
+    }
+
+    fn unpark(&mut self, block_id: &BlockID) -> Option<ParkedBlock> {
+        let parked = self.blocks.remove(block_id)?;
+        for dependency in &parked.dependencies {
+            if let Some(ids) = self.waiting.get_mut(dependency) {
+                ids.retain(|id| id != block_id);
+                if ids.is_empty() {
+                    self.waiting.remove(dependency);
+                }
+            }
+        }
+        Some(parked)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parked_block(priority: u32, dependencies: Vec<Dependency>, parked_at: Instant) -> ParkedBlock {
+        ParkedBlock {
+            block: SignedBlock::default(),
+            account: [0; 24],
+            priority,
+            dependencies,
+            parked_at,
+        }
+    }
+
+    #[test]
+    fn test_release_and_expire() {
+        let mut parked = ParkedBlocks::default();
+        let now = Instant::now();
+        let previous = Dependency::Block([1; 32]);
+        let send = Dependency::Transaction([2; 32]);
+
+        parked.park([3; 32], parked_block(1, vec![previous, send], now));
+        parked.park([4; 32], parked_block(2, vec![send], now + Duration::from_secs(10)));
+        assert_eq!(parked.len(), 2);
+        assert_eq!(parked.count_by_account(&[0; 24]), 2);
+        assert_eq!(parked.lowest_priority(), Some(([3; 32], 1)));
+
+        // a block is released by any of its dependencies
+        assert_eq!(parked.release(&previous).len(), 1);
+        assert_eq!(parked.len(), 1);
+        assert!(parked.release(&previous).is_empty());
+
+        assert!(parked.expire(now + Duration::from_secs(15), Duration::from_secs(10)).is_empty());
+        assert_eq!(parked.expire(now + Duration::from_secs(21), Duration::from_secs(10)), vec![[4; 32]]);
+        assert!(parked.is_empty());
+        assert!(parked.waiting.is_empty());
+    }
+}
@@ -1,19 +1,74 @@
-#![allow(dead_code)]
-
 use anyhow::{anyhow, Context, Result};
+use encoding::account::generate_account_address;
+use lazy_static::lazy_static;
+use pog_proto::api::{transaction::Data, AccountID, BlockID, SignedBlock, Transaction, TransactionID};
+use prometheus::{register_int_counter, register_int_gauge};
+use thiserror::Error;
 use tokio::sync::{
     mpsc::{self, Receiver, Sender},
     oneshot,
 };
-use tracing::info;
+use tracing::{debug, error, info, warn};
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use super::confirmation::{BlockStatus, ConfirmationPolicy, Decision, Immediate};
+use super::fork::Fork;
+use super::parked::{ParkedBlock, ParkedBlocks};
+use crate::{
+    config::BlockpoolSettings,
+    consensus::{
+        vote::{self, Vote, VoteResult, VoteTally},
+        voting_power::{get_actual_power, get_prime_delegate_power},
+    },
+    mode,
+    shutdown::Shutdown,
+    state::ChampStateArc,
+    storage::DatabaseError,
+    validation::block::{self, Dependency, Severity, Validation, ValidationReport},
+};
+
+const EVICTION_INTERVAL: Duration = Duration::from_secs(30);
+/// How long the voting power of an account is used to prioritize its blocks before it is calculated again
+const PRIORITY_TTL: Duration = Duration::from_secs(60);
+/// How long prime delegates can vote on competing blocks before the committed block is kept.
+/// This has to be the same on every node, so it can't be configured.
+const FORK_VOTE_TIMEOUT: Duration = Duration::from_secs(60);
+/// How many blocks below an account's latest block a competing block can start a fork vote,
+/// older committed blocks are final
+const MAX_FORK_DEPTH: u64 = 16;
 
-use std::collections::VecDeque;
+lazy_static! {
+    static ref QUEUED_BLOCKS: prometheus::IntGauge =
+        register_int_gauge!("blockpool_queued_blocks", "valid blocks waiting for confirmation").unwrap();
+    static ref PARKED_BLOCKS: prometheus::IntGauge =
+        register_int_gauge!("blockpool_parked_blocks", "blocks waiting for missing dependencies").unwrap();
+    static ref EXPIRED_BLOCKS: prometheus::IntCounter =
+        register_int_counter!("blockpool_expired_blocks", "blocks dropped after waiting too long").unwrap();
+    static ref REJECTED_BLOCKS: prometheus::IntCounter =
+        register_int_counter!("blockpool_rejected_blocks", "blocks rejected because the blockpool was full").unwrap();
+    static ref FORKS: prometheus::IntGauge =
+        register_int_gauge!("blockpool_forks", "account heights with competing blocks waiting for votes").unwrap();
+    static ref ROLLED_BACK_BLOCKS: prometheus::IntCounter =
+        register_int_counter!("blockpool_rolled_back_blocks", "committed blocks removed after losing a vote").unwrap();
+}
 
-use crate::{state::ChampStateArc, validation::block};
+#[derive(Error, Debug)]
+pub enum BlockpoolError {
+    #[error("blockpool is full")]
+    Full,
+    #[error("account has too many blocks in the blockpool")]
+    AccountLimitReached,
+}
 
 #[derive(Debug)]
 struct QueueItem {
-    block: pog_proto::api::SignedBlock,
+    block_id: BlockID,
+    block: SignedBlock,
+    account: AccountID,
+    priority: u32,
+    queued_at: Instant,
 }
 
 #[derive(Debug)]
@@ -21,6 +76,20 @@ pub struct Blockpool {
     pub tx: Sender<Command>,
     rx: Receiver<Command>,
     block_queue: VecDeque<QueueItem>,
+    // send transactions claimed by blocks in the queue
+    pending_claims: HashMap<TransactionID, BlockID>,
+    // blocks that arrived before their previous block or the sends they claim
+    parked: ParkedBlocks,
+    policy: Box<dyn ConfirmationPolicy>,
+    // clients waiting for the final outcome of a block
+    waiters: HashMap<BlockID, Vec<Responder<BlockStatus>>>,
+    // votes of prime delegates for blocks in the blockpool
+    votes: HashMap<BlockID, VoteTally>,
+    // blocks competing for the same height of an account
+    forks: Vec<Fork>,
+    // cached voting power of accounts with blocks in the blockpool
+    priorities: HashMap<AccountID, (u32, Instant)>,
+    settings: BlockpoolSettings,
     state: Option<ChampStateArc>,
 }
 
@@ -36,7 +105,13 @@ pub struct BlockpoolClient {
 }
 
 impl BlockpoolClient {
-    pub async fn process_block(&self, block: pog_proto::api::SignedBlock) -> Result<()> {
+    /// Validates a block and adds it to the queue if it is valid
+    ///
+    /// Returns every rule the block violates, an empty report means the block was accepted.
+    /// Fails with a [`BlockpoolError`] if there is no room for the block.
+    // waiting for the SubmitBlock call, see docs/developers/pog-proto.md
+    #[allow(dead_code)]
+    pub async fn process_block(&self, block: SignedBlock) -> Result<ValidationReport> {
         let (resp_tx, resp_rx) = oneshot::channel();
         self.tx
             .send(Command::ProcessBlock {
@@ -48,10 +123,96 @@ impl BlockpoolClient {
         resp_rx.await?
     }
 
-    pub async fn process_vote(&self) -> Result<()> {
+    /// Validates many blocks at once, e.g. while syncing
+    ///
+    /// Blocks are processed in order, so blocks of the same account have to be sorted by height.
+    /// Returns a result for each block, a block that fails, e.g. because the blockpool is full, doesn't stop the
+    /// following blocks from being processed.
+    // waiting for blocks to be received from other nodes
+    #[allow(dead_code)]
+    pub async fn process_blocks(&self, blocks: Vec<SignedBlock>) -> Result<Vec<Result<ValidationReport>>> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.tx
+            .send(Command::ProcessBlocks {
+                blocks,
+                resp: resp_tx,
+            })
+            .await
+            .with_context(|| "error sending process request")?;
+        resp_rx.await?
+    }
+
+    /// Waits until a block accepted by the blockpool is committed or dropped
+    ///
+    /// Blocks that were already committed return [`BlockStatus::Confirmed`] right away.
+    // waiting for the WatchBlock call, see docs/developers/pog-proto.md
+    #[allow(dead_code)]
+    pub async fn await_confirmation(&self, block_id: BlockID) -> Result<BlockStatus> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.tx
+            .send(Command::AwaitConfirmation {
+                block_id,
+                resp: resp_tx,
+            })
+            .await
+            .with_context(|| "error sending process request")?;
+        resp_rx.await?
+    }
+
+    /// Returns blocks that are not committed yet, either queued for confirmation or parked
+    ///
+    /// Only includes blocks of `account` if it is set.
+    pub async fn get_pending_blocks(
+        &self,
+        account: Option<AccountID>,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<SignedBlock>> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.tx
+            .send(Command::GetPendingBlocks {
+                account,
+                offset,
+                limit,
+                resp: resp_tx,
+            })
+            .await
+            .with_context(|| "error sending process request")?;
+        resp_rx.await?
+    }
+
+    /// Returns committed sends of an account that no block has claimed yet, newest first
+    // waiting for the account and pagination fields of GetUnacknowledgedTx, see docs/developers/pog-proto.md
+    #[allow(dead_code)]
+    pub async fn get_unacknowledged_txs(
+        &self,
+        account: AccountID,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<(TransactionID, Transaction)>> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.tx
+            .send(Command::GetUnacknowledgedTxs {
+                account,
+                offset,
+                limit,
+                resp: resp_tx,
+            })
+            .await
+            .with_context(|| "error sending process request")?;
+        resp_rx.await?
+    }
+
+    /// Verifies a prime delegate's vote and adds it to the votes for its block
+    ///
+    /// Returns whether the block received enough votes to be resolved.
+    // waiting for votes to be received from other nodes
+    #[allow(dead_code)]
+    pub async fn process_vote(&self, vote: Vote) -> Result<VoteResult> {
         let (resp_tx, resp_rx) = oneshot::channel();
         self.tx
             .send(Command::ProcessVote {
+                vote,
                 resp: resp_tx,
             })
             .await
@@ -73,11 +234,23 @@ impl BlockpoolClient {
 
 impl Blockpool {
     pub fn new() -> Self {
-        let (tx, rx) = mpsc::channel(1000);
+        Self::with_settings(BlockpoolSettings::default())
+    }
+
+    pub fn with_settings(settings: BlockpoolSettings) -> Self {
+        let (tx, rx) = mpsc::channel(settings.channel_size);
         Self {
             tx,
             rx,
-            block_queue: VecDeque::with_capacity(10_000),
+            block_queue: VecDeque::new(),
+            pending_claims: HashMap::new(),
+            parked: ParkedBlocks::default(),
+            policy: Box::new(Immediate),
+            waiters: HashMap::new(),
+            votes: HashMap::new(),
+            forks: Vec::new(),
+            priorities: HashMap::new(),
+            settings,
             state: None,
         }
     }
@@ -86,71 +259,1383 @@ impl Blockpool {
         self.state = Some(state);
     }
 
+    #[cfg(test)]
+    pub fn set_confirmation_policy(&mut self, policy: impl ConfirmationPolicy + 'static) {
+        self.policy = Box::new(policy);
+    }
+
     pub fn get_client(&self) -> BlockpoolClient {
         BlockpoolClient {
             tx: self.tx.clone(),
         }
     }
 
-    pub async fn start(&mut self) -> Result<(), Box<std::io::Error>> {
-        if self.state.is_none() {
-            panic!("add_state has to be called first")
+    /// Handles incoming commands until the node shuts down
+    ///
+    /// Blocks that are not confirmed yet are stored in the database on shutdown and processed again on the next start.
+    pub async fn start(&mut self, shutdown: Shutdown) -> Result<()> {
+        let state = self.state.clone().ok_or_else(|| anyhow!("add_state has to be called first"))?;
+        self.restore_pending_blocks().await?;
+
+        info!("blockpool started listening to incoming commands");
+        let mut eviction = tokio::time::interval(EVICTION_INTERVAL);
+        let shutdown = shutdown.recv();
+        tokio::pin!(shutdown);
+        loop {
+            tokio::select! {
+                cmd = self.rx.recv() => match cmd {
+                    Some(cmd) => self.handle_command(cmd).await,
+                    None => break,
+                },
+                _ = eviction.tick() => {
+                    self.evict_stale_blocks(Instant::now());
+                    if let Err(err) = self.resolve_expired_forks(Instant::now()).await {
+                        error!("forks could not be resolved: {err}");
+                    }
+                }
+                _ = &mut shutdown => break,
+            }
+            self.update_metrics();
         }
 
-        let state = self.state.clone().unwrap();
+        let pending = self.take_pending_blocks();
+        info!("blockpool stopped, storing {} pending blocks", pending.len());
+        state.db.lock().await.add_pending_blocks(pending).await?;
+        Ok(())
+    }
 
-        info!("blockpool started listening to incoming commands");
-        while let Some(cmd) = self.rx.recv().await {
-            use Command::*;
-            match cmd {
-                ProcessBlock {
-                    block,
-                    resp,
-                } => {
-                    let result = block::validate(&block, &state).await;
-                    match result {
-                        Ok(_) => {
-                            self.block_queue.push_back(QueueItem {
-                                block,
-                            });
-                            let _ = resp.send(Ok(()));
-                        } //TODO: Vote yes
-                        Err(block::BlockValidationError::Invalid(_)) => {
-                            let _ = resp.send(Ok(()));
-                        } //TODO: maybe retry or handle errors and then Start a vote
-                        Err(block::BlockValidationError::Error(err)) => {
-                            let _ = resp.send(Err(anyhow!("error {err}")));
-                        }
+    /// Processes the blocks stored on the last shutdown again
+    ///
+    /// A stored block is only removed from the database once it was processed, blocks that fail are kept for the next
+    /// start.
+    async fn restore_pending_blocks(&mut self) -> Result<()> {
+        let state = self.state.clone().ok_or_else(|| anyhow!("add_state has to be called first"))?;
+        let pending = state.db.lock().await.get_pending_blocks().await?;
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        info!("restoring {} pending blocks", pending.len());
+        for (index, block) in pending {
+            match self.process_block(block).await {
+                Ok(_) => state.db.lock().await.remove_pending_block(index).await?,
+                Err(err) => warn!("pending block could not be restored: {err}"),
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes all blocks that are not confirmed yet, queued blocks first
+    ///
+    /// Competing blocks come last and form the same fork again once they are restored.
+    fn take_pending_blocks(&mut self) -> Vec<SignedBlock> {
+        let parked = self.parked.blocks().into_iter().cloned().collect::<Vec<_>>();
+        self.parked = ParkedBlocks::default();
+        self.pending_claims.clear();
+        let competing = self.forks.drain(..).flat_map(|fork| fork.into_pending_blocks()).collect::<Vec<_>>();
+        self.block_queue.drain(..).map(|item| item.block).chain(parked).chain(competing).collect()
+    }
+
+    async fn handle_command(&mut self, cmd: Command) {
+        use Command::*;
+        match cmd {
+            ProcessBlock {
+                block,
+                resp,
+            } => {
+                let _ = resp.send(self.process_block(block).await);
+            }
+            ProcessBlocks {
+                blocks,
+                resp,
+            } => {
+                let _ = resp.send(self.process_blocks(blocks).await);
+            }
+            AwaitConfirmation {
+                block_id,
+                resp,
+            } => self.await_confirmation(block_id, resp).await,
+            GetPendingBlocks {
+                account,
+                offset,
+                limit,
+                resp,
+            } => {
+                let _ = resp.send(self.get_pending_blocks(account, offset, limit));
+            }
+            GetUnacknowledgedTxs {
+                account,
+                offset,
+                limit,
+                resp,
+            } => {
+                let _ = resp.send(self.get_unacknowledged_txs(account, offset, limit).await);
+            }
+            ProcessVote {
+                vote,
+                resp,
+            } => {
+                let _ = resp.send(self.process_vote(vote).await);
+            }
+            GetQueueSize {
+                resp,
+            } => {
+                let _ = resp.send(Ok(self.block_queue.len() as u64));
+            }
+        }
+    }
+
+    async fn process_block(&mut self, block: SignedBlock) -> Result<ValidationReport> {
+        self.process_blocks(vec![block]).await?.pop().ok_or_else(|| anyhow!("block was not processed"))?
+    }
+
+    async fn process_blocks(&mut self, blocks: Vec<SignedBlock>) -> Result<Vec<Result<ValidationReport>>> {
+        // verifying all signatures at once is a lot faster than verifying them one by one
+        let signatures = block::verify_signatures(&blocks).await.map_err(|err| anyhow!("error {err}"))?;
+
+        let mut results = Vec::with_capacity(blocks.len());
+        for (block, signature_valid) in blocks.into_iter().zip(signatures) {
+            results.push(self.process_verified_block(block, signature_valid).await);
+        }
+        Ok(results)
+    }
+
+    async fn process_verified_block(&mut self, block: SignedBlock, signature_valid: bool) -> Result<ValidationReport> {
+        // checked before anything else, so forged blocks can't take up capacity or evict other blocks
+        if !signature_valid {
+            REJECTED_BLOCKS.inc();
+            let mut report = ValidationReport::default();
+            report.add(Validation::InvalidSignature, None);
+            return Ok(report);
+        }
+
+        let account = generate_account_address(block.public_key.clone())?;
+        if let Some(state) = &self.state {
+            if !mode::tracks_account(state, &account).await {
+                REJECTED_BLOCKS.inc();
+                let mut report = ValidationReport::default();
+                report.add(Validation::UntrackedAccount, None);
+                return Ok(report);
+            }
+        }
+        let priority = self.get_priority(account).await;
+        self.reserve_capacity(&account, priority)?;
+
+        let report = self.admit_block(block, account, priority).await?;
+        if report.is_valid() {
+            self.confirm_queued_blocks().await?;
+        }
+        Ok(report)
+    }
+
+    /// Blocks competing in a fork are pending until the fork is resolved, even if one of them is committed
+    fn is_pending(&self, block_id: &BlockID) -> bool {
+        self.parked.contains(block_id)
+            || self.block_queue.iter().any(|item| &item.block_id == block_id)
+            || self.forks.iter().any(|fork| fork.contains(block_id))
+    }
+
+    async fn process_vote(&mut self, vote: Vote) -> Result<VoteResult> {
+        let state = self.state.clone().ok_or_else(|| anyhow!("add_state has to be called first"))?;
+        vote::verify_vote(&vote, &state).await?;
+
+        let block_id = vote.get_block_id()?;
+        let total_power = get_prime_delegate_power(&state).await?;
+        if self.forks.iter().any(|fork| fork.contains(&block_id)) {
+            return self.process_fork_vote(block_id, vote.get_voter()?, vote.power, total_power).await;
+        }
+
+        let tally = self.votes.entry(block_id).or_default();
+        tally.add(vote.get_voter()?, vote.power);
+        Ok(tally.result(total_power))
+    }
+
+    /// Adds a verified vote for one of the competing blocks of a fork and resolves the fork once a block has a quorum
+    async fn process_fork_vote(
+        &mut self,
+        block_id: BlockID,
+        voter: AccountID,
+        power: u32,
+        total_power: u64,
+    ) -> Result<VoteResult> {
+        let index = self
+            .forks
+            .iter()
+            .position(|fork| fork.contains(&block_id))
+            .ok_or_else(|| anyhow!("block is not part of a fork"))?;
+        let fork = &mut self.forks[index];
+        fork.add_vote(voter, block_id, power);
+
+        let result = fork.result(&block_id, total_power);
+        if let Some(winner) = fork.winner(total_power) {
+            let fork = self.forks.remove(index);
+            self.resolve_fork(fork, winner).await?;
+        }
+        Ok(result)
+    }
+
+    /// Resolves forks whose vote timed out without a quorum
+    ///
+    /// The committed block is kept. If none of the competing blocks is committed yet, all of them are rejected,
+    /// since an account that signs competing blocks can't expect either of them to be confirmed.
+    async fn resolve_expired_forks(&mut self, now: Instant) -> Result<()> {
+        let (expired, open): (Vec<Fork>, Vec<Fork>) = std::mem::take(&mut self.forks)
+            .into_iter()
+            .partition(|fork| now.saturating_duration_since(fork.detected_at) > FORK_VOTE_TIMEOUT);
+        self.forks = open;
+
+        for fork in expired {
+            match fork.committed {
+                Some(committed) => self.resolve_fork(fork, committed).await?,
+                None => {
+                    info!("fork vote timed out, rejecting all competing blocks");
+                    for (block_id, _) in fork.into_candidates() {
+                        self.notify(&block_id, BlockStatus::Rejected);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Keeps the block that won the vote and rejects the other competing blocks
+    ///
+    /// If the committed block lost, it is rolled back together with the blocks built on top of it.
+    /// Should the winning block turn out to be invalid, the rolled back blocks are restored
+    /// and the remaining blocks compete again.
+    async fn resolve_fork(&mut self, mut fork: Fork, winner: BlockID) -> Result<()> {
+        let state = self.state.clone().ok_or_else(|| anyhow!("add_state has to be called first"))?;
+        let winning_block = fork.take_candidate(&winner).ok_or_else(|| anyhow!("winner is not part of the fork"))?;
+        let (account, committed) = (fork.account, fork.committed);
+
+        if committed == Some(winner) {
+            info!("fork resolved in favor of the committed block");
+            self.notify(&winner, BlockStatus::Confirmed);
+            for (block_id, _) in fork.into_candidates() {
+                self.notify(&block_id, BlockStatus::Rejected);
+            }
+            return Ok(());
+        }
+
+        let mut rolled_back = Vec::new();
+        if committed.is_some() {
+            match roll_back(&state, account, fork.height).await {
+                Ok(blocks) => rolled_back = blocks,
+                Err(err) => {
+                    // the committed block stays, e.g. because other accounts already claimed its sends
+                    error!("losing block could not be rolled back: {err}");
+                    self.notify(&winner, BlockStatus::Rejected);
+                    for (block_id, _) in fork.into_candidates() {
+                        let status = if committed == Some(block_id) {
+                            BlockStatus::Confirmed
+                        } else {
+                            BlockStatus::Rejected
+                        };
+                        self.notify(&block_id, status);
+                    }
+                    return Ok(());
+                }
+            }
+            info!("rolled back {} blocks after a fork was resolved", rolled_back.len());
+            ROLLED_BACK_BLOCKS.inc_by(rolled_back.len() as u64);
+        }
+
+        let priority = self.get_priority(account).await;
+        let report = self.admit_block(winning_block, account, priority).await?;
+        if report.is_valid() {
+            for (block_id, _) in fork.into_candidates() {
+                self.notify(&block_id, BlockStatus::Rejected);
+            }
+            return self.confirm_queued_blocks().await;
+        }
+
+        warn!("winning block of a fork is invalid: {report}");
+        self.notify(&winner, BlockStatus::Rejected);
+        {
+            let mut db = state.db.lock().await;
+            for block in rolled_back {
+                db.add_block(block).await?;
+            }
+        }
+        for (block_id, block) in fork.into_candidates().filter(|(id, _)| Some(*id) != committed) {
+            let report = self.admit_block(block, account, priority).await?;
+            if !report.is_valid() && !self.is_pending(&block_id) {
+                self.notify(&block_id, BlockStatus::Rejected);
+            }
+        }
+        if let Some(committed) = committed.filter(|id| !self.is_pending(id)) {
+            self.notify(&committed, BlockStatus::Confirmed);
+        }
+        self.confirm_queued_blocks().await
+    }
+
+    async fn await_confirmation(&mut self, block_id: BlockID, resp: Responder<BlockStatus>) {
+        if self.is_pending(&block_id) {
+            self.waiters.entry(block_id).or_default().push(resp);
+            return;
+        }
+
+        let state = match self.state.clone() {
+            Some(state) => state,
+            None => {
+                let _ = resp.send(Err(anyhow!("add_state has to be called first")));
+                return;
+            }
+        };
+        let result = match state.db.lock().await.get_block_by_id(block_id).await {
+            Ok(_) => Ok(BlockStatus::Confirmed),
+            Err(DatabaseError::BlockNotFound) => Err(anyhow!("block is unknown to the blockpool")),
+            Err(err) => Err(anyhow!("error {err}")),
+        };
+        let _ = resp.send(result);
+    }
+
+    /// Commits all queued blocks the confirmation policy confirms and drops the ones it rejects
+    ///
+    /// Blocks are only queued once everything they depend on is committed, so committing them in any order is safe.
+    /// Committing a block releases the parked blocks waiting for it, which are confirmed in the same pass.
+    async fn confirm_queued_blocks(&mut self) -> Result<()> {
+        let state = self.state.clone().ok_or_else(|| anyhow!("add_state has to be called first"))?;
+
+        let mut index = 0;
+        while index < self.block_queue.len() {
+            let status = match self.policy.decide(&self.block_queue[index].block) {
+                Decision::Confirm => BlockStatus::Confirmed,
+                Decision::Reject => BlockStatus::Rejected,
+                Decision::Wait => {
+                    index += 1;
+                    continue;
+                }
+            };
+
+            let item = self.block_queue.remove(index).ok_or_else(|| anyhow!("queued block disappeared"))?;
+            for (_, send_id) in block::get_claimed_sends(&item.block) {
+                self.pending_claims.remove(&send_id);
+            }
+
+            if status == BlockStatus::Confirmed {
+                let provided = block::get_provided_dependencies(&item.block).map_err(|err| anyhow!("error {err}"))?;
+                let committed = state.db.lock().await.add_block(item.block).await;
+                if let Err(err) = committed {
+                    error!("block could not be committed: {err}");
+                    for resp in self.waiters.remove(&item.block_id).unwrap_or_default() {
+                        let _ = resp.send(Err(anyhow!("block could not be committed")));
                     }
+                    continue;
+                }
+                self.release_parked(provided).await?;
+            }
+            self.notify(&item.block_id, status);
+        }
+        Ok(())
+    }
+
+    fn get_pending_blocks(&self, account: Option<AccountID>, offset: usize, limit: usize) -> Result<Vec<SignedBlock>> {
+        let queued = self.block_queue.iter().map(|item| &item.block);
+        let mut blocks = Vec::new();
+        for block in queued.chain(self.parked.blocks()) {
+            if let Some(account) = account {
+                if generate_account_address(block.public_key.clone())? != account {
+                    continue;
                 }
-                ProcessVote {
-                    resp,
-                } => {
-                    let _ = resp.send(Err(anyhow!("not implemented")));
+            }
+            blocks.push(block);
+        }
+        Ok(blocks.into_iter().skip(offset).take(limit).cloned().collect())
+    }
+
+    async fn get_unacknowledged_txs(
+        &self,
+        account: AccountID,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<(TransactionID, Transaction)>> {
+        let state = self.state.clone().ok_or_else(|| anyhow!("add_state has to be called first"))?;
+        let db = state.db.lock().await;
+
+        let latest = match db.get_latest_block_by_account(account).await {
+            Ok(block) => block,
+            Err(DatabaseError::NoLastBlock) => return Ok(vec![]),
+            Err(err) => return Err(err.into()),
+        };
+        let latest_height = latest.data.as_ref().ok_or_else(|| anyhow!("missing block data"))?.height;
+
+        let mut skipped = 0;
+        let mut txs = Vec::new();
+        for height in (0..=latest_height).rev() {
+            let block = match db.get_block_by_height(account, &height).await? {
+                Some(block) => block,
+                None => continue,
+            };
+            let block_id = block.get_id().map_err(|_| anyhow!("block id could not be created"))?;
+
+            for tx in block.data.map(|data| data.transactions).unwrap_or_default() {
+                if !matches!(tx.data, Some(Data::TxSend(_))) {
+                    continue;
+                }
+                let tx_id = tx.get_id(block_id).map_err(|_| anyhow!("transaction id could not be created"))?;
+                // sends claimed by a queued block are acknowledged even though the claim isn't committed yet
+                if self.pending_claims.contains_key(&tx_id) || db.get_send_recipient(tx_id).await?.is_some() {
+                    continue;
+                }
+                if skipped < offset {
+                    skipped += 1;
+                    continue;
                 }
-                GetQueueSize {
-                    resp,
-                } => {
-                    let _ = resp.send(Ok(self.block_queue.len() as u64));
+                txs.push((tx_id, tx));
+                if txs.len() >= limit {
+                    return Ok(txs);
+                }
+            }
+        }
+        Ok(txs)
+    }
+
+    /// Blocks of accounts with more voting power are confirmed first and evicted last
+    async fn get_priority(&mut self, account: AccountID) -> u32 {
+        if let Some((priority, calculated_at)) = self.priorities.get(&account) {
+            if calculated_at.elapsed() < PRIORITY_TTL {
+                return *priority;
+            }
+        }
+
+        let priority = match &self.state {
+            // new accounts don't have any voting power yet
+            Some(state) => get_actual_power(state, account).await.unwrap_or(0),
+            None => 0,
+        };
+        self.priorities.insert(account, (priority, Instant::now()));
+        priority
+    }
+
+    /// Makes sure there is room for another block of an account
+    ///
+    /// If the blockpool is full, the parked block with the lowest priority is dropped if it has less priority.
+    /// Valid blocks waiting for confirmation are never dropped to make room.
+    fn reserve_capacity(&mut self, account: &AccountID, priority: u32) -> Result<(), BlockpoolError> {
+        let account_blocks = self.block_queue.iter().filter(|item| &item.account == account).count()
+            + self.parked.count_by_account(account)
+            + self.forks.iter().filter(|fork| &fork.account == account).map(|fork| fork.pending_len()).sum::<usize>();
+        if account_blocks >= self.settings.max_blocks_per_account {
+            REJECTED_BLOCKS.inc();
+            return Err(BlockpoolError::AccountLimitReached);
+        }
+
+        if self.len() < self.settings.max_blocks {
+            return Ok(());
+        }
+        match self.parked.lowest_priority() {
+            Some((block_id, lowest)) if lowest < priority => {
+                debug!("dropping parked block to make room for a block with a higher priority");
+                self.parked.remove(&block_id);
+                EXPIRED_BLOCKS.inc();
+                self.notify(&block_id, BlockStatus::Expired);
+                Ok(())
+            }
+            _ => {
+                REJECTED_BLOCKS.inc();
+                Err(BlockpoolError::Full)
+            }
+        }
+    }
+
+    /// Number of blocks the blockpool holds, including the competing blocks of forks that aren't committed
+    fn len(&self) -> usize {
+        self.block_queue.len() + self.parked.len() + self.forks.iter().map(|fork| fork.pending_len()).sum::<usize>()
+    }
+
+    fn update_metrics(&self) {
+        QUEUED_BLOCKS.set(self.block_queue.len() as i64);
+        PARKED_BLOCKS.set(self.parked.len() as i64);
+        FORKS.set(self.forks.len() as i64);
+    }
+
+    fn notify(&mut self, block_id: &BlockID, status: BlockStatus) {
+        self.votes.remove(block_id);
+        for resp in self.waiters.remove(block_id).unwrap_or_default() {
+            let _ = resp.send(Ok(status));
+        }
+    }
+
+    /// Validates a block and queues it if it is valid or parks it if it is only missing dependencies
+    ///
+    /// The signature of the block has to be verified already, so forged blocks can't start a fork vote.
+    async fn admit_block(
+        &mut self,
+        block: SignedBlock,
+        account: AccountID,
+        priority: u32,
+    ) -> Result<ValidationReport> {
+        let state = self.state.clone().ok_or_else(|| anyhow!("add_state has to be called first"))?;
+        let block_id = block.get_id().map_err(|_| anyhow!("block id could not be created"))?;
+
+        if self.track_fork(&block, block_id, account).await? {
+            debug!("block competes with another block at the same height");
+            let mut report = ValidationReport::default();
+            report.add(Validation::ConflictingBlock, None);
+            return Ok(report);
+        }
+
+        let mut report =
+            block::validate_with_signature(&block, &state, true).await.map_err(|err| anyhow!("error {err}"))?;
+        // queued blocks are not in the database yet, so their claims have to be checked separately
+        report.extend(block::verify_pending_claims(&block, &self.pending_claims));
+
+        if report.is_valid() {
+            for (_, send_id) in block::get_claimed_sends(&block) {
+                self.pending_claims.insert(send_id, block_id);
+            }
+            // blocks with the same priority are confirmed in the order they arrived
+            let position =
+                self.block_queue.iter().position(|item| item.priority < priority).unwrap_or(self.block_queue.len());
+            self.block_queue.insert(
+                position,
+                QueueItem {
+                    block_id,
+                    block,
+                    account,
+                    priority,
+                    queued_at: Instant::now(),
+                },
+            );
+            //TODO: Vote yes
+        } else if report.severity() == Some(Severity::MissingDependency) {
+            let dependencies = block::get_missing_dependencies(&block, &report);
+            if !dependencies.is_empty() {
+                debug!("parking block until its dependencies arrive");
+                self.parked.park(
+                    block_id,
+                    ParkedBlock {
+                        block,
+                        account,
+                        priority,
+                        dependencies,
+                        parked_at: Instant::now(),
+                    },
+                );
+            }
+        }
+        //TODO: maybe retry or handle errors and then Start a vote
+
+        Ok(report)
+    }
+
+    /// Detects blocks that compete with a queued or committed block of the same account at the same height
+    ///
+    /// Competing blocks have to share their previous block. They are held back until prime delegates voted on which
+    /// one is kept, a competing queued block is taken out of the queue until then. Committed blocks more than
+    /// [`MAX_FORK_DEPTH`] blocks below the account's latest block can't be challenged anymore.
+    /// Returns whether the block is part of a fork.
+    async fn track_fork(&mut self, block: &SignedBlock, block_id: BlockID, account: AccountID) -> Result<bool> {
+        let state = self.state.clone().ok_or_else(|| anyhow!("add_state has to be called first"))?;
+        let data = block.data.as_ref().ok_or_else(|| anyhow!("missing block data"))?;
+
+        let competing = |other: &SignedBlock| {
+            other.data.as_ref().map(|other| other.height == data.height && other.previous == data.previous)
+                == Some(true)
+        };
+
+        let fork = self.forks.iter_mut().find(|fork| fork.account == account && fork.height == data.height);
+        if let Some(fork) = fork {
+            if !fork.candidates().any(|(_, candidate)| competing(candidate)) {
+                return Ok(false);
+            }
+            fork.add_candidate(block_id, block.clone());
+            return Ok(true);
+        }
+
+        let queued = self
+            .block_queue
+            .iter()
+            .position(|item| item.account == account && item.block_id != block_id && competing(&item.block));
+        if let Some(index) = queued {
+            let item = self.block_queue.remove(index).ok_or_else(|| anyhow!("queued block disappeared"))?;
+            for (_, send_id) in block::get_claimed_sends(&item.block) {
+                self.pending_claims.remove(&send_id);
+            }
+            let mut fork = Fork::new(account, data.height, None, Instant::now());
+            fork.add_candidate(item.block_id, item.block);
+            fork.add_candidate(block_id, block.clone());
+            self.forks.push(fork);
+            return Ok(true);
+        }
+
+        let db = state.db.lock().await;
+        let committed = db.get_block_by_height(account, &data.height).await?;
+        let committed = match committed {
+            Some(committed) if competing(&committed) => committed,
+            _ => return Ok(false),
+        };
+        let committed_id = committed.get_id().map_err(|_| anyhow!("block id could not be created"))?;
+        if committed_id == block_id {
+            return Ok(false);
+        }
+        let latest = db.get_latest_block_by_account(account).await?;
+        let latest_height = latest.data.as_ref().ok_or_else(|| anyhow!("missing block data"))?.height;
+        if latest_height.saturating_sub(data.height) >= MAX_FORK_DEPTH {
+            debug!("block competes with a final block at height {}", data.height);
+            return Ok(false);
+        }
+
+        let mut fork = Fork::new(account, data.height, Some(committed_id), Instant::now());
+        fork.add_candidate(committed_id, committed);
+        fork.add_candidate(block_id, block.clone());
+        self.forks.push(fork);
+        Ok(true)
+    }
+
+    /// Validates all parked blocks waiting for one of the committed dependencies again
+    ///
+    /// Valid blocks are added to the queue, blocks that are still missing something stay parked.
+    async fn release_parked(&mut self, dependencies: Vec<Dependency>) -> Result<()> {
+        for dependency in dependencies {
+            for parked in self.parked.release(&dependency) {
+                let block_id = parked.block.get_id().map_err(|_| anyhow!("block id could not be created"))?;
+                // blocks with an invalid signature are never parked
+                let report = self.admit_block(parked.block, parked.account, parked.priority).await?;
+                if !report.is_valid() && !self.is_pending(&block_id) {
+                    debug!("parked block is invalid: {report}");
+                    self.notify(&block_id, BlockStatus::Rejected);
                 }
             }
         }
         Ok(())
     }
+
+    /// Drops parked and queued blocks that are older than the configured max age
+    fn evict_stale_blocks(&mut self, now: Instant) {
+        let max_age = Duration::from_secs(self.settings.max_block_age);
+        let mut evicted = self.parked.expire(now, max_age);
+
+        let (stale, fresh): (VecDeque<QueueItem>, VecDeque<QueueItem>) =
+            self.block_queue.drain(..).partition(|item| now.saturating_duration_since(item.queued_at) > max_age);
+        self.block_queue = fresh;
+        for item in stale {
+            for (_, send_id) in block::get_claimed_sends(&item.block) {
+                self.pending_claims.remove(&send_id);
+            }
+            evicted.push(item.block_id);
+        }
+
+        if !evicted.is_empty() {
+            debug!("dropped {} stale blocks", evicted.len());
+            EXPIRED_BLOCKS.inc_by(evicted.len() as u64);
+        }
+        for block_id in &evicted {
+            self.notify(block_id, BlockStatus::Expired);
+        }
+        self.priorities.retain(|_, (_, calculated_at)| now.saturating_duration_since(*calculated_at) < PRIORITY_TTL);
+
+        // votes can arrive before their block, but not for longer than an eviction interval
+        let votes = std::mem::take(&mut self.votes);
+        self.votes = votes.into_iter().filter(|(id, _)| self.is_pending(id)).collect();
+    }
+}
+
+/// Removes the committed blocks of an account down to the given height
+///
+/// Returns the removed blocks, oldest first. If a block can't be removed, the already removed blocks are restored.
+async fn roll_back(state: &ChampStateArc, account: AccountID, height: u64) -> Result<Vec<SignedBlock>> {
+    let mut db = state.db.lock().await;
+    let mut removed = Vec::new();
+    loop {
+        let latest = db.get_latest_block_by_account(account).await?;
+        let latest_height = latest.data.as_ref().ok_or_else(|| anyhow!("missing block data"))?.height;
+        if latest_height < height {
+            break;
+        }
+
+        let block_id = latest.get_id().map_err(|_| anyhow!("block id could not be created"))?;
+        if let Err(err) = db.remove_block(block_id).await {
+            for block in removed.into_iter().rev() {
+                db.add_block(block).await?;
+            }
+            return Err(err.into());
+        }
+        removed.push(latest);
+        if latest_height == height {
+            break;
+        }
+    }
+    removed.reverse();
+    Ok(removed)
 }
 
 type Responder<T> = oneshot::Sender<Result<T>>;
 
+/// Requests a `BlockpoolClient` sends to the blockpool
+///
+/// Variants allowed to be dead are only sent by client methods that don't have a caller yet.
 #[derive(Debug)]
 pub enum Command {
+    #[allow(dead_code)]
     ProcessBlock {
-        block: pog_proto::api::SignedBlock,
-        resp: Responder<()>,
+        block: SignedBlock,
+        resp: Responder<ValidationReport>,
+    },
+    #[allow(dead_code)]
+    ProcessBlocks {
+        blocks: Vec<SignedBlock>,
+        resp: Responder<Vec<Result<ValidationReport>>>,
+    },
+    #[allow(dead_code)]
+    AwaitConfirmation {
+        block_id: BlockID,
+        resp: Responder<BlockStatus>,
+    },
+    GetPendingBlocks {
+        account: Option<AccountID>,
+        offset: usize,
+        limit: usize,
+        resp: Responder<Vec<SignedBlock>>,
     },
+    #[allow(dead_code)]
+    GetUnacknowledgedTxs {
+        account: AccountID,
+        offset: usize,
+        limit: usize,
+        resp: Responder<Vec<(TransactionID, Transaction)>>,
+    },
+    #[allow(dead_code)]
     ProcessVote {
-        resp: Responder<()>,
+        vote: Vote,
+        resp: Responder<VoteResult>,
     },
     GetQueueSize {
         resp: Responder<u64>,
     },
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::prime_delegates::{PrimeDelegate, PrimeDelegates};
+    use crate::shutdown;
+    use crate::state::ChampState;
+    use crate::validation::block::{Validation, Violation};
+    use crypto::signatures::ed25519::{create_public_key, create_signature, generate_private_key};
+    use encoding::account::generate_account_address;
+    use pog_proto::api::{
+        signed_block::BlockData,
+        transaction::{Data, TxClaim, TxDelegate, TxSend},
+        AccountID, Transaction,
+    };
+    use pog_proto::rpc::node_admin::Mode;
+    use prost::Message;
+
+    struct TestAccount {
+        private_key: [u8; 32],
+        public_key: [u8; 32],
+    }
+
+    impl TestAccount {
+        fn new() -> Self {
+            let private_key = generate_private_key().expect("should generate private key");
+            let public_key = create_public_key(&private_key).expect("should calculate public key");
+            Self {
+                private_key,
+                public_key,
+            }
+        }
+
+        fn address(&self) -> AccountID {
+            generate_account_address(self.public_key.to_vec()).expect("should generate address")
+        }
+
+        fn sign(&self, height: u64, balance: u64, previous: Vec<u8>, transactions: Vec<Transaction>) -> SignedBlock {
+            let data = BlockData {
+                version: 0,
+                signature_type: 0,
+                balance,
+                height,
+                previous,
+                transactions,
+            };
+            let signature = create_signature(&data.encode_to_vec(), &self.private_key).expect("should sign block");
+            SignedBlock {
+                signature: signature.to_vec(),
+                public_key: self.public_key.to_vec(),
+                timestamp: 1,
+                data: Some(data),
+            }
+        }
+    }
+
+    /// Keeps every block in the queue like a vote that never finishes
+    #[derive(Debug)]
+    struct Hold;
+
+    impl ConfirmationPolicy for Hold {
+        fn decide(&mut self, _block: &SignedBlock) -> Decision {
+            Decision::Wait
+        }
+    }
+
+    /// Processes a batch of blocks that all have to be processed without an error
+    async fn process_all(pool: &mut Blockpool, blocks: Vec<SignedBlock>) -> Vec<ValidationReport> {
+        let results = pool.process_blocks(blocks).await.expect("should process blocks");
+        results.into_iter().collect::<Result<_>>().expect("should process every block")
+    }
+
+    #[tokio::test]
+    async fn test_pending_double_claim() {
+        let state = ChampState::mock().await;
+        let mut pool = Blockpool::new();
+        pool.add_state(state.clone());
+        pool.set_confirmation_policy(Hold);
+
+        let sender = TestAccount::new();
+        let receiver = TestAccount::new();
+
+        let send = Transaction {
+            data: Some(Data::TxSend(TxSend {
+                receiver: receiver.address().to_vec(),
+                amount: 10,
+                data: vec![],
+            })),
+        };
+        let sender_genesis = sender.sign(0, 90, vec![], vec![send.clone()]);
+        let receiver_genesis = receiver.sign(0, 0, vec![], vec![]);
+        let send_id = send.get_id(sender_genesis.get_id().expect("get block ID")).expect("get tx ID");
+        let previous = receiver_genesis.get_id().expect("get block ID").to_vec();
+        {
+            let mut db = state.db.lock().await;
+            db.add_block(sender_genesis).await.expect("block should be added");
+            db.add_block(receiver_genesis).await.expect("block should be added");
+        }
+
+        let claim = Transaction {
+            data: Some(Data::TxClaim(TxClaim {
+                send_transaction_id: send_id.to_vec(),
+            })),
+        };
+
+        let first = receiver.sign(1, 10, previous, vec![claim.clone()]);
+        // builds on the queued first block and claims the same send again
+        let second = receiver.sign(2, 20, first.get_id().expect("get block ID").to_vec(), vec![claim]);
+
+        let report = pool.process_block(first).await.expect("should process block");
+        assert!(report.is_valid(), "{}", report);
+
+        let report = pool.process_block(second).await.expect("should process block");
+        assert!(report.violations.contains(&Violation {
+            rule: Validation::PendingDoubleClaim,
+            tx_index: Some(0),
+        }));
+        assert_eq!(report.severity(), Some(Severity::Invalid));
+        // the block is rejected instead of waiting for the first block to be committed
+        assert!(pool.parked.is_empty());
+        assert_eq!(pool.block_queue.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_process_blocks() {
+        let state = ChampState::mock().await;
+        let mut pool = Blockpool::new();
+        pool.add_state(state.clone());
+
+        let account = TestAccount::new();
+        let genesis = account.sign(0, 0, vec![], vec![]);
+        let next = account.sign(1, 0, genesis.get_id().expect("get block ID").to_vec(), vec![]);
+        let mut forged = TestAccount::new().sign(0, 0, vec![], vec![]);
+        forged.signature = next.signature.clone();
+        state.db.lock().await.add_block(genesis).await.expect("block should be added");
+
+        let reports = process_all(&mut pool, vec![next, forged]).await;
+        assert!(reports[0].is_valid(), "{}", reports[0]);
+        assert_eq!(
+            reports[1].violations,
+            vec![Violation {
+                rule: Validation::InvalidSignature,
+                tx_index: None,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_parked_block_released() {
+        let state = ChampState::mock().await;
+        let mut pool = Blockpool::new();
+        pool.add_state(state.clone());
+
+        let account = TestAccount::new();
+        let genesis = account.sign(0, 0, vec![], vec![]);
+        let first = account.sign(1, 0, genesis.get_id().expect("get block ID").to_vec(), vec![]);
+        let second = account.sign(2, 0, first.get_id().expect("get block ID").to_vec(), vec![]);
+        let second_id = second.get_id().expect("get block ID");
+        state.db.lock().await.add_block(genesis).await.expect("block should be added");
+
+        // the second block arrives before the first one
+        let report = pool.process_block(second).await.expect("should process block");
+        assert_eq!(report.severity(), Some(Severity::MissingDependency));
+        assert_eq!(pool.parked.len(), 1);
+
+        let (resp_tx, resp_rx) = oneshot::channel();
+        pool.await_confirmation(second_id, resp_tx).await;
+
+        // committing the first block releases the second one
+        let report = pool.process_block(first).await.expect("should process block");
+        assert!(report.is_valid(), "{}", report);
+        assert!(pool.parked.is_empty());
+        assert!(pool.block_queue.is_empty());
+        assert_eq!(resp_rx.await.expect("should respond").expect("should confirm"), BlockStatus::Confirmed);
+
+        let latest = state.db.lock().await.get_latest_block_by_account(account.address()).await;
+        assert_eq!(latest.expect("should have blocks").get_id().expect("get block ID"), second_id);
+    }
+
+    #[tokio::test]
+    async fn test_stale_block_evicted() {
+        let state = ChampState::mock().await;
+        let mut pool = Blockpool::new();
+        pool.add_state(state.clone());
+
+        let orphan = TestAccount::new().sign(1, 0, vec![1; 32], vec![]);
+        let orphan_id = orphan.get_id().expect("get block ID");
+        let report = pool.process_block(orphan).await.expect("should process block");
+        assert_eq!(report.severity(), Some(Severity::MissingDependency));
+        assert_eq!(pool.parked.len(), 1);
+
+        let (resp_tx, resp_rx) = oneshot::channel();
+        pool.await_confirmation(orphan_id, resp_tx).await;
+
+        pool.evict_stale_blocks(Instant::now());
+        assert_eq!(pool.parked.len(), 1);
+        pool.evict_stale_blocks(Instant::now() + Duration::from_secs(pool.settings.max_block_age * 2));
+        assert!(pool.parked.is_empty());
+        assert_eq!(resp_rx.await.expect("should respond").expect("should expire"), BlockStatus::Expired);
+    }
+
+    #[tokio::test]
+    async fn test_get_pending_blocks() {
+        let state = ChampState::mock().await;
+        let mut pool = Blockpool::new();
+        pool.add_state(state.clone());
+        pool.set_confirmation_policy(Hold);
+
+        let account = TestAccount::new();
+        let queued = account.sign(0, 0, vec![], vec![]);
+        let parked = TestAccount::new().sign(1, 0, vec![1; 32], vec![]);
+        process_all(&mut pool, vec![queued.clone(), parked.clone()]).await;
+
+        assert_eq!(pool.get_pending_blocks(None, 0, 10).expect("should get blocks"), vec![queued.clone(), parked]);
+        assert_eq!(pool.get_pending_blocks(None, 1, 10).expect("should get blocks").len(), 1);
+        assert_eq!(pool.get_pending_blocks(Some(account.address()), 0, 10).expect("should get blocks"), vec![queued]);
+    }
+
+    #[tokio::test]
+    async fn test_get_unacknowledged_txs() {
+        let state = ChampState::mock().await;
+        let mut pool = Blockpool::new();
+        pool.add_state(state.clone());
+
+        let sender = TestAccount::new();
+        let receiver = TestAccount::new();
+        let send = |amount| Transaction {
+            data: Some(Data::TxSend(TxSend {
+                receiver: receiver.address().to_vec(),
+                amount,
+                data: vec![],
+            })),
+        };
+
+        let sender_genesis = sender.sign(0, 70, vec![], vec![send(10), send(20)]);
+        let sender_genesis_id = sender_genesis.get_id().expect("get block ID");
+        let first_id = send(10).get_id(sender_genesis_id).expect("get tx ID");
+        let second_id = send(20).get_id(sender_genesis_id).expect("get tx ID");
+        let receiver_genesis = receiver.sign(0, 0, vec![], vec![]);
+        let claim = receiver.sign(
+            1,
+            10,
+            receiver_genesis.get_id().expect("get block ID").to_vec(),
+            vec![Transaction {
+                data: Some(Data::TxClaim(TxClaim {
+                    send_transaction_id: first_id.to_vec(),
+                })),
+            }],
+        );
+
+        let reports = process_all(&mut pool, vec![sender_genesis, receiver_genesis]).await;
+        assert!(reports.iter().all(|r| r.is_valid()));
+
+        let txs = pool.get_unacknowledged_txs(sender.address(), 0, 10).await.expect("should get txs");
+        assert_eq!(txs.iter().map(|(id, _)| *id).collect::<Vec<_>>(), vec![first_id, second_id]);
+        assert_eq!(pool.get_unacknowledged_txs(sender.address(), 1, 10).await.expect("should get txs").len(), 1);
+
+        let report = pool.process_block(claim).await.expect("should process block");
+        assert!(report.is_valid(), "{}", report);
+        let txs = pool.get_unacknowledged_txs(sender.address(), 0, 10).await.expect("should get txs");
+        assert_eq!(txs.iter().map(|(id, _)| *id).collect::<Vec<_>>(), vec![second_id]);
+    }
+
+    #[tokio::test]
+    async fn test_blockpool_limits() {
+        let state = ChampState::mock().await;
+        let mut pool = Blockpool::with_settings(BlockpoolSettings {
+            max_blocks: 2,
+            max_blocks_per_account: 1,
+            ..Default::default()
+        });
+        pool.add_state(state.clone());
+
+        let account = TestAccount::new();
+        let orphan = |previous| account.sign(1, 0, vec![previous; 32], vec![]);
+        pool.process_block(orphan(1)).await.expect("should process block");
+        let err = pool.process_block(orphan(2)).await.expect_err("should reach account limit");
+        assert!(matches!(err.downcast_ref::<BlockpoolError>(), Some(BlockpoolError::AccountLimitReached)));
+
+        pool.process_block(TestAccount::new().sign(1, 0, vec![1; 32], vec![])).await.expect("should process block");
+        let err =
+            pool.process_block(TestAccount::new().sign(1, 0, vec![1; 32], vec![])).await.expect_err("should be full");
+        assert!(matches!(err.downcast_ref::<BlockpoolError>(), Some(BlockpoolError::Full)));
+        assert_eq!(pool.parked.len(), 2);
+
+        // forged blocks are rejected before they could take up room
+        let mut forged = TestAccount::new().sign(1, 0, vec![1; 32], vec![]);
+        forged.signature = vec![0; 64];
+        let report = pool.process_block(forged).await.expect("should process block");
+        assert_eq!(report.violations[0].rule, Validation::InvalidSignature);
+        assert_eq!(pool.parked.len(), 2);
+
+        // blocks with a higher priority replace parked blocks
+        pool.reserve_capacity(&TestAccount::new().address(), 10).expect("should make room");
+        assert_eq!(pool.parked.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_process_blocks_when_full() {
+        let state = ChampState::mock().await;
+        let mut pool = Blockpool::with_settings(BlockpoolSettings {
+            max_blocks: 1,
+            ..Default::default()
+        });
+        pool.add_state(state.clone());
+
+        let orphan = || TestAccount::new().sign(1, 0, vec![1; 32], vec![]);
+        let mut forged = orphan();
+        forged.signature = vec![0; 64];
+        let results = pool.process_blocks(vec![orphan(), orphan(), forged]).await.expect("should process blocks");
+
+        // the block that doesn't fit doesn't stop the blocks after it from being processed
+        assert_eq!(results.len(), 3);
+        assert!(results[0].is_ok());
+        let err = results[1].as_ref().expect_err("should be full");
+        assert!(matches!(err.downcast_ref::<BlockpoolError>(), Some(BlockpoolError::Full)));
+        let report = results[2].as_ref().expect("should process block");
+        assert_eq!(report.violations[0].rule, Validation::InvalidSignature);
+        assert_eq!(pool.parked.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_forks_count_towards_limit() {
+        let state = ChampState::mock().await;
+        let mut pool = Blockpool::with_settings(BlockpoolSettings {
+            max_blocks: 2,
+            ..Default::default()
+        });
+        pool.add_state(state.clone());
+        pool.set_confirmation_policy(Hold);
+
+        let account = TestAccount::new();
+        let genesis = account.sign(0, 0, vec![], vec![]);
+        let (first, second) = competing_blocks(&account, genesis.get_id().expect("get block ID").to_vec());
+        state.db.lock().await.add_block(genesis).await.expect("block should be added");
+
+        pool.process_block(first).await.expect("should process block");
+        pool.process_block(second).await.expect("should process block");
+        assert!(pool.block_queue.is_empty());
+        assert_eq!(pool.len(), 2);
+
+        let err =
+            pool.process_block(TestAccount::new().sign(1, 0, vec![1; 32], vec![])).await.expect_err("should be full");
+        assert!(matches!(err.downcast_ref::<BlockpoolError>(), Some(BlockpoolError::Full)));
+    }
+
+    #[tokio::test]
+    async fn test_light_mode() {
+        let state = ChampState::mock().await;
+        state.mode.set(Mode::Light);
+        let mut pool = Blockpool::new();
+        pool.add_state(state.clone());
+
+        // every block of the batch is rejected on its own
+        let blocks =
+            vec![TestAccount::new().sign(0, 0, vec![], vec![]), TestAccount::new().sign(0, 0, vec![], vec![])];
+        let reports = process_all(&mut pool, blocks).await;
+        for report in reports {
+            assert_eq!(report.violations[0].rule, Validation::UntrackedAccount);
+            assert_eq!(report.severity(), Some(Severity::Invalid));
+        }
+        assert_eq!(pool.len(), 0);
+
+        state.mode.set(Mode::Validating);
+        let report = pool.process_block(TestAccount::new().sign(0, 0, vec![], vec![])).await.expect("should process");
+        assert!(report.is_valid(), "{}", report);
+    }
+
+    #[tokio::test]
+    async fn test_pending_blocks_persisted() {
+        let state = ChampState::mock().await;
+        let mut pool = Blockpool::new();
+        pool.add_state(state.clone());
+
+        let orphan = TestAccount::new().sign(1, 0, vec![1; 32], vec![]);
+        pool.process_block(orphan.clone()).await.expect("should process block");
+
+        let (trigger, shutdown) = shutdown::channel();
+        trigger.trigger();
+        pool.start(shutdown).await.expect("should shut down");
+        assert!(pool.parked.is_empty());
+
+        let mut restarted = Blockpool::new();
+        restarted.add_state(state.clone());
+        restarted.restore_pending_blocks().await.expect("should restore blocks");
+        assert_eq!(restarted.get_pending_blocks(None, 0, 10).expect("should get blocks"), vec![orphan]);
+        assert!(state.db.lock().await.get_pending_blocks().await.expect("should get blocks").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_restore_keeps_blocks() {
+        let state = ChampState::mock().await;
+        let blocks: Vec<SignedBlock> = (1..=2).map(|i| TestAccount::new().sign(1, 0, vec![i; 32], vec![])).collect();
+        state.db.lock().await.add_pending_blocks(blocks.clone()).await.expect("should store blocks");
+
+        // only one of the blocks fits
+        let mut pool = Blockpool::with_settings(BlockpoolSettings {
+            max_blocks: 1,
+            ..Default::default()
+        });
+        pool.add_state(state.clone());
+        pool.restore_pending_blocks().await.expect("should restore blocks");
+        assert_eq!(pool.get_pending_blocks(None, 0, 10).expect("should get blocks"), vec![blocks[0].clone()]);
+
+        let stored = state.db.lock().await.get_pending_blocks().await.expect("should get blocks");
+        assert_eq!(stored, vec![(1, blocks[1].clone())]);
+    }
+
+    /// Two competing blocks of an account, each delegating to a different representative
+    fn competing_blocks(account: &TestAccount, previous: Vec<u8>) -> (SignedBlock, SignedBlock) {
+        let delegate = || Transaction {
+            data: Some(Data::TxDelegate(TxDelegate {
+                representative: TestAccount::new().address().to_vec(),
+            })),
+        };
+        (account.sign(1, 0, previous.clone(), vec![delegate()]), account.sign(1, 0, previous, vec![delegate()]))
+    }
+
+    async fn latest_block_id(state: &ChampStateArc, account: &TestAccount) -> BlockID {
+        let latest = state.db.lock().await.get_latest_block_by_account(account.address()).await;
+        latest.expect("should have blocks").get_id().expect("get block ID")
+    }
+
+    /// Stores the prime delegates of epoch 1 with the given voting power
+    async fn add_prime_delegates(state: &ChampStateArc, delegates: &[(&TestAccount, u32)]) {
+        let prime_delegates = PrimeDelegates {
+            epoch: 1,
+            delegates: delegates
+                .iter()
+                .map(|(account, power)| PrimeDelegate {
+                    account: account.address().to_vec(),
+                    power: *power,
+                })
+                .collect(),
+            max_power: 0,
+        };
+        state.db.lock().await.set_prime_delegates(prime_delegates).await.expect("should set prime delegates");
+    }
+
+    /// A vote of a prime delegate of epoch 1 for a block at height 1
+    async fn sign_vote(state: &ChampStateArc, voter: &TestAccount, power: u32, block_id: BlockID) -> Vote {
+        let chain = state.config.read().await.consensus.chain_identity().expect("should get chain identity");
+        let mut vote = Vote {
+            block_id: block_id.to_vec(),
+            height: 1,
+            voter: voter.address().to_vec(),
+            power,
+            public_key: voter.public_key.to_vec(),
+            signature: vec![],
+            epoch: 1,
+            chain: chain.to_vec(),
+        };
+        vote.sign(&voter.private_key).expect("should sign vote");
+        vote
+    }
+
+    #[tokio::test]
+    async fn test_fork_resolution_simulation() {
+        let account = TestAccount::new();
+        let genesis = account.sign(0, 0, vec![], vec![]);
+        let (x, y) = competing_blocks(&account, genesis.get_id().expect("get block ID").to_vec());
+        let (x_id, y_id) = (x.get_id().expect("get block ID"), y.get_id().expect("get block ID"));
+        let child = account.sign(2, 0, x_id.to_vec(), vec![]);
+        let delegates = [TestAccount::new(), TestAccount::new(), TestAccount::new()];
+        let powers = [(&delegates[0], 40), (&delegates[1], 35), (&delegates[2], 25)];
+
+        let mut nodes = Vec::new();
+        for _ in 0..3 {
+            let state = ChampState::mock().await;
+            state.db.lock().await.add_block(genesis.clone()).await.expect("block should be added");
+            add_prime_delegates(&state, &powers).await;
+            let mut pool = Blockpool::new();
+            pool.add_state(state.clone());
+            nodes.push((state, pool));
+        }
+
+        // the first node commits x and a block built on top of it before y arrives
+        let reports = process_all(&mut nodes[0].1, vec![x.clone(), child, y.clone()]).await;
+        assert!(reports[0].is_valid() && reports[1].is_valid());
+        assert_eq!(reports[2].violations[0].rule, Validation::ConflictingBlock);
+        // the other nodes see y first
+        for (_, pool) in &mut nodes[1..] {
+            let reports = process_all(pool, vec![y.clone(), x.clone()]).await;
+            assert!(reports[0].is_valid(), "{}", reports[0]);
+            assert_eq!(reports[1].violations[0].rule, Validation::ConflictingBlock);
+        }
+
+        let (resp_tx, resp_rx) = oneshot::channel();
+        nodes[0].1.await_confirmation(x_id, resp_tx).await;
+
+        // every prime delegate votes for the block it saw first, the votes reach every node
+        let mut choices = vec![x_id, y_id, y_id];
+        for (state, pool) in &mut nodes {
+            for ((voter, power), block_id) in powers.iter().zip(&choices) {
+                let vote = sign_vote(state, voter, *power, *block_id).await;
+                let result = pool.process_vote(vote).await.expect("should vote");
+                assert!(matches!(result, VoteResult::Unresolved { .. }));
+            }
+            assert_eq!(pool.forks.len(), 1);
+        }
+
+        // the first delegate follows the majority, which resolves the fork
+        choices[0] = y_id;
+        for (state, pool) in &mut nodes {
+            let vote = sign_vote(state, &delegates[0], 40, choices[0]).await;
+            let result = pool.process_vote(vote).await.expect("should vote");
+            assert_eq!(
+                result,
+                VoteResult::Resolved {
+                    power: 100
+                }
+            );
+            assert!(pool.forks.is_empty());
+            assert_eq!(latest_block_id(state, &account).await, y_id);
+            assert!(state.db.lock().await.get_block_by_id(x_id).await.is_err());
+        }
+        assert_eq!(resp_rx.await.expect("should respond").expect("should reject"), BlockStatus::Rejected);
+    }
+
+    #[tokio::test]
+    async fn test_expired_fork_keeps_committed_block() {
+        let account = TestAccount::new();
+        let genesis = account.sign(0, 0, vec![], vec![]);
+        let (x, y) = competing_blocks(&account, genesis.get_id().expect("get block ID").to_vec());
+        let (x_id, y_id) = (x.get_id().expect("get block ID"), y.get_id().expect("get block ID"));
+        let delegates = [TestAccount::new(), TestAccount::new()];
+        let powers = [(&delegates[0], 50), (&delegates[1], 50)];
+
+        for (blocks, committed) in [(vec![x.clone(), y.clone()], x_id), (vec![y.clone(), x.clone()], y_id)] {
+            let state = ChampState::mock().await;
+            state.db.lock().await.add_block(genesis.clone()).await.expect("block should be added");
+            add_prime_delegates(&state, &powers).await;
+            let mut pool = Blockpool::new();
+            pool.add_state(state.clone());
+            process_all(&mut pool, blocks).await;
+
+            // a tie is no quorum, no matter which block id is lower
+            for ((voter, power), block_id) in powers.iter().zip([x_id, y_id]) {
+                pool.process_vote(sign_vote(&state, voter, *power, block_id).await).await.expect("should vote");
+            }
+
+            pool.resolve_expired_forks(Instant::now()).await.expect("should resolve forks");
+            assert_eq!(pool.forks.len(), 1);
+            pool.resolve_expired_forks(Instant::now() + FORK_VOTE_TIMEOUT * 2).await.expect("should resolve forks");
+            assert!(pool.forks.is_empty());
+            assert_eq!(latest_block_id(&state, &account).await, committed);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_expired_fork_without_committed_block() {
+        let account = TestAccount::new();
+        let genesis = account.sign(0, 0, vec![], vec![]);
+        let (x, y) = competing_blocks(&account, genesis.get_id().expect("get block ID").to_vec());
+        let y_id = y.get_id().expect("get block ID");
+
+        let state = ChampState::mock().await;
+        state.db.lock().await.add_block(genesis.clone()).await.expect("block should be added");
+        let mut pool = Blockpool::new();
+        pool.add_state(state.clone());
+        pool.set_confirmation_policy(Hold);
+        process_all(&mut pool, vec![x, y]).await;
+        assert_eq!(pool.forks.len(), 1);
+        assert!(pool.forks[0].committed.is_none());
+
+        let (resp_tx, resp_rx) = oneshot::channel();
+        pool.await_confirmation(y_id, resp_tx).await;
+        pool.resolve_expired_forks(Instant::now() + FORK_VOTE_TIMEOUT * 2).await.expect("should resolve forks");
+        assert!(pool.forks.is_empty());
+        assert_eq!(pool.len(), 0);
+        assert_eq!(resp_rx.await.expect("should respond").expect("should reject"), BlockStatus::Rejected);
+        assert_eq!(latest_block_id(&state, &account).await, genesis.get_id().expect("get block ID"));
+    }
+
+    #[tokio::test]
+    async fn test_fork_depth_is_bounded() {
+        let account = TestAccount::new();
+        let mut blocks = vec![account.sign(0, 0, vec![], vec![])];
+        for height in 1..=MAX_FORK_DEPTH + 1 {
+            let previous = blocks.last().expect("should have blocks").get_id().expect("get block ID");
+            blocks.push(account.sign(height, 0, previous.to_vec(), vec![]));
+        }
+
+        let state = ChampState::mock().await;
+        for block in &blocks {
+            state.db.lock().await.add_block(block.clone()).await.expect("block should be added");
+        }
+        let mut pool = Blockpool::new();
+        pool.add_state(state.clone());
+
+        // competes with the block at height 1, which is MAX_FORK_DEPTH blocks below the latest one
+        let (_, final_competitor) = competing_blocks(&account, blocks[0].get_id().expect("get block ID").to_vec());
+        let report = pool.process_block(final_competitor).await.expect("should process");
+        assert!(!report.is_valid());
+        assert!(!report.violations.iter().any(|violation| violation.rule == Validation::ConflictingBlock));
+        assert!(pool.forks.is_empty());
+
+        // competes with the block at height 2, which can still be challenged
+        let delegate = Transaction {
+            data: Some(Data::TxDelegate(TxDelegate {
+                representative: TestAccount::new().address().to_vec(),
+            })),
+        };
+        let competitor = account.sign(2, 0, blocks[1].get_id().expect("get block ID").to_vec(), vec![delegate]);
+        let report = pool.process_block(competitor).await.expect("should process");
+        assert_eq!(report.violations[0].rule, Validation::ConflictingBlock);
+        assert_eq!(pool.forks.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_process_vote_verifies_signature() {
+        let state = ChampState::mock().await;
+        let mut pool = Blockpool::new();
+        pool.add_state(state.clone());
+
+        let voter = TestAccount::new();
+        let mut vote = Vote {
+            block_id: vec![1; 32],
+            height: 0,
+            voter: voter.address().to_vec(),
+            power: 10,
+            public_key: voter.public_key.to_vec(),
+            signature: vec![],
+            epoch: 0,
+            chain: vec![],
+        };
+        vote.sign(&voter.private_key).expect("should sign vote");
+        vote.power = 20;
+
+        let err = pool.process_vote(vote).await.expect_err("should reject vote");
+        assert!(matches!(err.downcast_ref::<vote::VoteError>(), Some(vote::VoteError::InvalidSignature)));
+        assert!(pool.votes.is_empty());
+    }
+}
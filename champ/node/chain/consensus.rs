@@ -0,0 +1,195 @@
+use crate::chain::ChainError;
+use crypto::hash::sha3;
+use serde::{Deserialize, Serialize};
+
+/// Weights and curve shapes used to calculate voting power
+///
+/// Every node of a chain has to use the same params, otherwise they disagree on the voting power of accounts.
+/// They are part of the chain identity, see [`ConsensusParams::chain_identity`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ConsensusParams {
+    /// weight of the block graph in an account's actual power
+    pub block_weight: f64,
+    /// weight of the balance graph in an account's actual power
+    pub balance_weight: f64,
+    /// weight of the cashflow graph in an account's actual power
+    pub cashflow_weight: f64,
+    /// weight of the age graph in an account's actual power
+    pub age_weight: f64,
+    /// weight of the inactive tax in an account's actual power
+    pub inactive_tax_weight: f64,
+
+    /// share of the prime delegates' combined power a single account can have, 0.3 = 30%
+    pub max_network_power: f64,
+    /// the cashflow is the change of the balance over this many seconds
+    pub lookback_range: u64,
+
+    /// steepness of the block graph's edges
+    pub tx_curve_max: u32,
+    /// blocks per week at which the block graph reaches its plateau
+    pub plateau_size: f64,
+    /// share of an account's power it loses while inactive, 0.1 = 10%
+    pub inactive_tax_percentage: f64,
+    /// share of the balance the cashflow has to exceed to avoid the inactive tax
+    pub inactive_tax_bound: f64,
+
+    pub normalize_balance: f64,
+    pub normalize_cashflow: f64,
+    pub normalize_inactive_tax: f64,
+    pub normalize_block: f64,
+    pub normalize_age: f64,
+
+    /// reduce the power accounts delegate by their farming score
+    pub dampen_farming: bool,
+}
+
+impl Default for ConsensusParams {
+    fn default() -> Self {
+        Self::dev()
+    }
+}
+
+impl ConsensusParams {
+    /// Returns the consensus parameters of a chain, e.g. `dev`
+    pub fn for_chain(chain: &str) -> Result<Self, ChainError> {
+        match chain {
+            "dev" => Ok(Self::dev()),
+            _ => Err(ChainError::UnknownChain(chain.to_string())),
+        }
+    }
+
+    fn dev() -> Self {
+        Self {
+            block_weight: 1.2,
+            balance_weight: 0.75,
+            cashflow_weight: 1.0,
+            age_weight: 1.0,
+            inactive_tax_weight: 1.0,
+            max_network_power: 0.3,
+            lookback_range: 60 * 60 * 24 * 30,
+            tx_curve_max: 15,
+            plateau_size: 175.0,
+            inactive_tax_percentage: 0.1,
+            inactive_tax_bound: 0.1,
+            normalize_balance: 1.0,
+            normalize_cashflow: 1.0,
+            normalize_inactive_tax: 1.0,
+            normalize_block: 10.0,
+            normalize_age: 1.0,
+            dampen_farming: false,
+        }
+    }
+
+    /// Checks that the params result in a usable voting power calculation
+    pub fn validate(&self) -> Result<(), ChainError> {
+        let invalid = |reason: &str| Err(ChainError::InvalidConsensusParams(reason.to_string()));
+
+        let weights =
+            [self.block_weight, self.balance_weight, self.cashflow_weight, self.age_weight, self.inactive_tax_weight];
+        if weights.iter().any(|weight| !weight.is_finite() || *weight < 0.0) {
+            return invalid("weights must not be negative");
+        }
+
+        let normalizers = [
+            self.normalize_balance,
+            self.normalize_cashflow,
+            self.normalize_inactive_tax,
+            self.normalize_block,
+            self.normalize_age,
+        ];
+        if normalizers.iter().any(|normalizer| !normalizer.is_finite() || *normalizer <= 0.0) {
+            return invalid("normalizers have to be greater than 0");
+        }
+
+        let is_share = |share: f64| (0.0..=1.0).contains(&share);
+        if !is_share(self.max_network_power) || self.max_network_power == 0.0 {
+            return invalid("max_network_power has to be greater than 0 and at most 1");
+        }
+        if !is_share(self.inactive_tax_percentage) || !is_share(self.inactive_tax_bound) {
+            return invalid("inactive_tax_percentage and inactive_tax_bound have to be between 0 and 1");
+        }
+        if self.lookback_range == 0 {
+            return invalid("lookback_range has to be greater than 0");
+        }
+        if self.tx_curve_max == 0 || !self.plateau_size.is_finite() || self.plateau_size <= 0.0 {
+            return invalid("tx_curve_max and plateau_size have to be greater than 0");
+        }
+        Ok(())
+    }
+
+    /// Hash identifying a chain together with its consensus params
+    ///
+    /// Nodes only accept votes carrying their own chain identity, so nodes with different params never interoperate.
+    pub fn chain_identity(&self, chain: &str) -> [u8; 32] {
+        let mut data = Vec::new();
+        data.extend((chain.len() as u64).to_be_bytes());
+        data.extend(chain.as_bytes());
+
+        let floats = [
+            self.block_weight,
+            self.balance_weight,
+            self.cashflow_weight,
+            self.age_weight,
+            self.inactive_tax_weight,
+            self.max_network_power,
+            self.plateau_size,
+            self.inactive_tax_percentage,
+            self.inactive_tax_bound,
+            self.normalize_balance,
+            self.normalize_cashflow,
+            self.normalize_inactive_tax,
+            self.normalize_block,
+            self.normalize_age,
+        ];
+        for float in floats {
+            data.extend(float.to_bits().to_be_bytes());
+        }
+        data.extend(self.lookback_range.to_be_bytes());
+        data.extend(self.tx_curve_max.to_be_bytes());
+        data.push(self.dampen_farming as u8);
+
+        sha3(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_params() {
+        assert!(ConsensusParams::for_chain("dev").expect("dev chain should exist").validate().is_ok());
+
+        let params = ConsensusParams {
+            max_network_power: 0.0,
+            ..Default::default()
+        };
+        assert!(params.validate().is_err());
+
+        let params = ConsensusParams {
+            lookback_range: 0,
+            ..Default::default()
+        };
+        assert!(params.validate().is_err());
+
+        let params = ConsensusParams {
+            normalize_balance: 0.0,
+            ..Default::default()
+        };
+        assert!(params.validate().is_err());
+    }
+
+    #[test]
+    fn test_chain_identity() {
+        let params = ConsensusParams::default();
+        assert_eq!(params.chain_identity("dev"), ConsensusParams::default().chain_identity("dev"));
+        assert_ne!(params.chain_identity("dev"), params.chain_identity("test"));
+
+        let changed = ConsensusParams {
+            block_weight: 1.3,
+            ..Default::default()
+        };
+        assert_ne!(params.chain_identity("dev"), changed.chain_identity("dev"));
+    }
+}
@@ -0,0 +1,18 @@
+mod consensus;
+mod protocol;
+mod versions;
+pub use consensus::*;
+pub use protocol::*;
+pub use versions::*;
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ChainError {
+    #[error("unknown chain: {0}")]
+    UnknownChain(String),
+    #[error("invalid consensus params: {0}")]
+    InvalidConsensusParams(String),
+    #[error("invalid version schedule: {0}")]
+    InvalidVersionSchedule(String),
+}
@@ -0,0 +1,39 @@
+use crate::chain::ChainError;
+
+/// Limits every block on a chain has to respect
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtocolParams {
+    /// max number of transactions in a block
+    pub max_transactions: usize,
+    /// max size of the `data` field of a send transaction
+    pub max_payload_bytes: usize,
+    /// max size of an encoded block including its signature
+    pub max_block_bytes: usize,
+    /// min amount of a send transaction
+    pub min_send_amount: u64,
+    /// min seconds between two delegations of an account
+    pub redelegation_cooldown: u64,
+    /// max seconds a block timestamp may be ahead of the node's clock
+    pub max_clock_skew: u64,
+}
+
+impl ProtocolParams {
+    /// Returns the protocol parameters of a chain, e.g. `dev`
+    pub fn for_chain(chain: &str) -> Result<Self, ChainError> {
+        match chain {
+            "dev" => Ok(Self::dev()),
+            _ => Err(ChainError::UnknownChain(chain.to_string())),
+        }
+    }
+
+    fn dev() -> Self {
+        Self {
+            max_transactions: 255,
+            max_payload_bytes: 256,
+            max_block_bytes: 128 * 1024,
+            min_send_amount: 1,
+            redelegation_cooldown: 60 * 60 * 24,
+            max_clock_skew: 60 * 5,
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use crate::chain::ChainError;
+use pog_proto::api::BlockVersion;
+use serde::{Deserialize, Serialize};
+
+/// The point in time from which on blocks have to use a version
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct VersionActivation {
+    #[serde(with = "block_version")]
+    pub version: BlockVersion,
+    /// unix timestamp from which on blocks use this version
+    pub activation_timestamp: u64,
+}
+
+/// Block versions are stored by their number in the config, `BlockVersion` doesn't implement serde
+mod block_version {
+    use pog_proto::api::BlockVersion;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(version: &BlockVersion, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i32(*version as i32)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BlockVersion, D::Error> {
+        let version = i32::deserialize(deserializer)?;
+        BlockVersion::from_i32(version).ok_or_else(|| D::Error::custom(format!("unknown block version {version}")))
+    }
+}
+
+/// The block versions of a chain
+///
+/// A version is valid from its activation until the next version activates.
+/// This allows shipping protocol upgrades ahead of time.
+/// Blocks are checked against the version that was active when the account's previous block was created,
+/// since the timestamp of a block itself is chosen by its creator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionSchedule {
+    activations: Vec<VersionActivation>,
+}
+
+impl VersionSchedule {
+    pub fn new(mut activations: Vec<VersionActivation>) -> Self {
+        activations.sort_by_key(|a| a.activation_timestamp);
+        Self {
+            activations,
+        }
+    }
+
+    /// Returns the version schedule of a chain, e.g. `dev`
+    pub fn for_chain(chain: &str) -> Result<Self, ChainError> {
+        match chain {
+            "dev" => Ok(Self::new(vec![VersionActivation {
+                version: BlockVersion::V1,
+                activation_timestamp: 0,
+            }])),
+            _ => Err(ChainError::UnknownChain(chain.to_string())),
+        }
+    }
+
+    /// Checks that the schedule contains at least one version
+    pub fn validate(&self) -> Result<(), ChainError> {
+        if self.activations.is_empty() {
+            return Err(ChainError::InvalidVersionSchedule("no block version is scheduled".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Returns the version that is active at `timestamp`
+    pub fn active_version(&self, timestamp: u64) -> Option<BlockVersion> {
+        self.activations.iter().rev().find(|a| a.activation_timestamp <= timestamp).map(|a| a.version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_active_version() {
+        let schedule = VersionSchedule::new(vec![VersionActivation {
+            version: BlockVersion::V1,
+            activation_timestamp: 100,
+        }]);
+
+        assert_eq!(schedule.active_version(99), None);
+        assert_eq!(schedule.active_version(100), Some(BlockVersion::V1));
+        assert_eq!(schedule.active_version(u64::MAX), Some(BlockVersion::V1));
+    }
+}
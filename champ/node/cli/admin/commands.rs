@@ -1,5 +1,8 @@
 use crate::{
-    cli::{admin::create_user, error::CLIError},
+    cli::{
+        admin::{create_user, simulate_power},
+        error::CLIError,
+    },
     state::ChampStateArc,
 };
 
@@ -51,5 +54,16 @@ pub async fn run(matches: &ArgMatches, state: &ChampStateArc) -> Result<(), CLIE
         return Ok(());
     }
 
+    if let Some(matches) = matches.subcommand_matches("simulate-power") {
+        debug!("simulating voting power");
+        let scenario =
+            matches.value_of("scenario").ok_or_else(|| CLIError::Unknown("scenario missing".to_string()))?;
+        let format = match matches.value_of("format") {
+            Some("json") => simulate_power::OutputFormat::Json,
+            _ => simulate_power::OutputFormat::Csv,
+        };
+        return simulate_power::run(state, scenario, format, matches.value_of("output")).await;
+    }
+
     Err(CLIError::UnknownCommand)
 }
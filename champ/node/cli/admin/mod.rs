@@ -1,3 +1,4 @@
 mod commands;
 mod create_user;
+mod simulate_power;
 pub use commands::*;
@@ -0,0 +1,392 @@
+use std::collections::{HashMap, HashSet};
+
+use crypto::signatures::ed25519::{create_public_key, create_signature, generate_private_key};
+use encoding::account::generate_account_address;
+use pog_proto::api::{
+    signed_block::BlockData, transaction::Data, AccountID, BlockVersion, SignedBlock, Transaction, TxDelegate,
+};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use tracing::{debug, trace};
+
+use crate::blockpool::Blockpool;
+use crate::chain::ConsensusParams;
+use crate::cli::error::CLIError;
+use crate::config::Config;
+use crate::consensus::voting_power::{get_active_power_breakdown, get_actual_power_breakdown};
+use crate::state::{ChampState, ChampStateArc, ChampStateArgs};
+use crate::storage::{self, DatabaseConfig, Databases};
+use crate::wallets::WalletManager;
+
+/// Accounts and their block histories to calculate voting power for
+#[derive(Deserialize, Debug)]
+pub struct Scenario {
+    /// replaces the consensus params of the configured chain
+    #[serde(default)]
+    pub params: Option<ConsensusParams>,
+    pub time: SimulationTime,
+    pub accounts: Vec<ScenarioAccount>,
+}
+
+/// Points in time the voting power is calculated at, unix timestamps
+#[derive(Deserialize, Debug)]
+pub struct SimulationTime {
+    pub start: u64,
+    pub end: u64,
+    pub step: u64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ScenarioAccount {
+    pub name: String,
+    /// sorted by timestamp
+    #[serde(default)]
+    pub blocks: Vec<ScenarioBlock>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ScenarioBlock {
+    pub timestamp: u64,
+    pub balance: u64,
+    /// name of the account to delegate to
+    #[serde(default)]
+    pub delegate: Option<String>,
+}
+
+/// Voting power of an account at a point in time
+///
+/// The graph results are converted to floating point numbers for the output only.
+#[derive(Serialize, Debug)]
+pub struct PowerRow {
+    pub time: u64,
+    pub account: String,
+    pub balance_graph: Option<f64>,
+    pub cashflow_graph: Option<f64>,
+    pub block_graph: Option<f64>,
+    pub age_graph: Option<f64>,
+    pub inactive_tax_graph: Option<f64>,
+    pub actual: Option<u32>,
+    /// combined actual power of the accounts delegating to the account
+    pub delegated: Option<u32>,
+    /// max voting power of a single account, empty until the first prime delegates are selected
+    pub max_power: Option<u32>,
+    pub active: Option<u32>,
+    /// why the voting power could not be calculated
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum OutputFormat {
+    Csv,
+    Json,
+}
+
+pub async fn run(
+    state: &ChampStateArc,
+    scenario_path: &str,
+    format: OutputFormat,
+    output: Option<&str>,
+) -> Result<(), CLIError> {
+    let scenario = std::fs::read_to_string(scenario_path)
+        .map_err(|e| CLIError::InvalidScenario(format!("could not read {scenario_path}: {e}")))?;
+    let scenario = toml::from_str::<Scenario>(&scenario).map_err(|e| CLIError::InvalidScenario(e.to_string()))?;
+
+    let config = state.config.read().await.clone();
+    let rows = simulate(config, scenario).await?;
+
+    let output_data = match format {
+        OutputFormat::Csv => to_csv(&rows),
+        OutputFormat::Json => serde_json::to_string_pretty(&rows).map_err(|e| CLIError::Unknown(e.to_string()))?,
+    };
+    match output {
+        Some(path) => std::fs::write(path, output_data).map_err(|e| CLIError::Unknown(e.to_string()))?,
+        None => println!("{output_data}"),
+    }
+    Ok(())
+}
+
+/// Adds the scenario's blocks to a temporary database and calculates the voting power of every account at every
+/// point in time, after all blocks up to that time were added
+pub async fn simulate(mut config: Config, scenario: Scenario) -> Result<Vec<PowerRow>, CLIError> {
+    validate_scenario(&scenario)?;
+    if let Some(params) = scenario.params {
+        config.consensus.params = Some(params);
+    }
+    config.consensus.consensus_params().map_err(|e| CLIError::InvalidScenario(e.to_string()))?;
+
+    let state = temporary_state(config).await?;
+    let mut accounts = scenario.accounts.iter().map(SimulatedAccount::new).collect::<Result<Vec<_>, _>>()?;
+    let addresses = accounts.iter().map(|account| (account.name.clone(), account.address)).collect::<HashMap<_, _>>();
+
+    // blocks of all accounts ordered by time, blocks at the same time keep the scenario's order
+    let mut blocks = scenario
+        .accounts
+        .iter()
+        .enumerate()
+        .flat_map(|(index, account)| account.blocks.iter().map(move |block| (index, block)))
+        .collect::<Vec<_>>();
+    blocks.sort_by_key(|(_, block)| block.timestamp);
+    let mut blocks = blocks.into_iter().peekable();
+
+    let mut rows = Vec::new();
+    let mut time = scenario.time.start;
+    while time <= scenario.time.end {
+        while let Some((index, block)) = blocks.next_if(|(_, block)| block.timestamp <= time) {
+            let delegate = block.delegate.as_ref().map(|name| addresses[name]);
+            let signed_block = accounts[index].next_block(block, delegate)?;
+            trace!("adding block of {} at {}", accounts[index].name, block.timestamp);
+            state.db.lock().await.add_block(signed_block).await.map_err(|e| CLIError::Unknown(e.to_string()))?;
+        }
+
+        for account in accounts.iter().filter(|account| account.height > 0) {
+            rows.push(calculate_row(&state, time, account).await);
+        }
+
+        time = match time.checked_add(scenario.time.step) {
+            Some(time) => time,
+            None => break,
+        };
+    }
+    Ok(rows)
+}
+
+fn validate_scenario(scenario: &Scenario) -> Result<(), CLIError> {
+    let invalid = |reason: String| Err(CLIError::InvalidScenario(reason));
+    if scenario.time.step == 0 || scenario.time.start > scenario.time.end {
+        return invalid("time.step has to be greater than 0 and time.start can't be after time.end".to_string());
+    }
+
+    let mut names = HashSet::new();
+    for account in &scenario.accounts {
+        if !names.insert(&account.name) {
+            return invalid(format!("account {} exists twice", account.name));
+        }
+        if account.blocks.windows(2).any(|pair| pair[0].timestamp > pair[1].timestamp) {
+            return invalid(format!("blocks of {} are not sorted by timestamp", account.name));
+        }
+    }
+    for account in &scenario.accounts {
+        for delegate in account.blocks.iter().filter_map(|block| block.delegate.as_ref()) {
+            if !names.contains(delegate) {
+                return invalid(format!("{} delegates to unknown account {delegate}", account.name));
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn temporary_state(config: Config) -> Result<ChampStateArc, CLIError> {
+    debug!("creating temporary database for the simulation");
+    let db = storage::new(&DatabaseConfig {
+        kind: Databases::Sled,
+        temporary: Some(true),
+        ..Default::default()
+    })
+    .await
+    .map_err(|e| CLIError::Unknown(e.to_string()))?;
+
+    // the blockpool is never started, the simulation only reads from the database
+    Ok(ChampState::new(ChampStateArgs {
+        db,
+        wallet_manager: RwLock::new(WalletManager::new(config.wallets.clone())),
+        config: RwLock::new(config),
+        blockpool_client: Blockpool::new().get_client(),
+    }))
+}
+
+async fn calculate_row(state: &ChampStateArc, time: u64, account: &SimulatedAccount) -> PowerRow {
+    let mut row = PowerRow {
+        time,
+        account: account.name.clone(),
+        balance_graph: None,
+        cashflow_graph: None,
+        block_graph: None,
+        age_graph: None,
+        inactive_tax_graph: None,
+        actual: None,
+        delegated: None,
+        max_power: None,
+        active: None,
+        error: None,
+    };
+
+    let breakdown = match get_actual_power_breakdown(state, account.address).await {
+        Ok(breakdown) => breakdown,
+        Err(err) => {
+            row.error = Some(err.to_string());
+            return row;
+        }
+    };
+    row.balance_graph = Some(breakdown.balance.to_f64());
+    row.cashflow_graph = Some(breakdown.cashflow.to_f64());
+    row.block_graph = Some(breakdown.block.to_f64());
+    row.age_graph = Some(breakdown.age.to_f64());
+    row.inactive_tax_graph = Some(breakdown.inactive_tax.to_f64());
+    row.actual = Some(breakdown.power);
+
+    match get_active_power_breakdown(state, account.address).await {
+        Ok(breakdown) => {
+            row.delegated = Some(breakdown.delegated);
+            row.max_power = breakdown.max_power;
+            row.active = Some(breakdown.power);
+        }
+        Err(err) => row.error = Some(err.to_string()),
+    }
+    row
+}
+
+/// A scenario account with its own key pair, so its blocks are stored like real ones
+struct SimulatedAccount {
+    name: String,
+    address: AccountID,
+    private_key: [u8; 32],
+    public_key: Vec<u8>,
+    height: u64,
+    previous: Vec<u8>,
+}
+
+impl SimulatedAccount {
+    fn new(account: &ScenarioAccount) -> Result<Self, CLIError> {
+        let private_key =
+            generate_private_key().map_err(|_| CLIError::Unknown("could not generate key".to_string()))?;
+        let public_key = create_public_key(&private_key)
+            .map_err(|_| CLIError::Unknown("could not generate key".to_string()))?
+            .to_vec();
+        let address = generate_account_address(public_key.clone()).map_err(|e| CLIError::Unknown(e.to_string()))?;
+        Ok(Self {
+            name: account.name.clone(),
+            address,
+            private_key,
+            public_key,
+            height: 0,
+            previous: vec![],
+        })
+    }
+
+    fn next_block(&mut self, block: &ScenarioBlock, delegate: Option<AccountID>) -> Result<SignedBlock, CLIError> {
+        let transactions = delegate
+            .map(|representative| Transaction {
+                data: Some(Data::TxDelegate(TxDelegate {
+                    representative: representative.to_vec(),
+                })),
+            })
+            .into_iter()
+            .collect();
+        let data = BlockData {
+            version: BlockVersion::V1 as i32,
+            signature_type: 0,
+            balance: block.balance,
+            height: self.height,
+            previous: self.previous.clone(),
+            transactions,
+        };
+        let signature = create_signature(&data.encode_to_vec(), &self.private_key)
+            .map_err(|_| CLIError::Unknown("could not sign block".to_string()))?;
+        let signed_block = SignedBlock {
+            signature: signature.to_vec(),
+            public_key: self.public_key.clone(),
+            timestamp: block.timestamp,
+            data: Some(data),
+        };
+
+        self.previous = signed_block.get_id().map_err(|e| CLIError::Unknown(e.to_string()))?.to_vec();
+        self.height += 1;
+        Ok(signed_block)
+    }
+}
+
+fn to_csv(rows: &[PowerRow]) -> String {
+    fn field<T: ToString>(value: &Option<T>) -> String {
+        value.as_ref().map(|value| value.to_string()).unwrap_or_default()
+    }
+    // names and errors are quoted since they could contain commas
+    fn quoted(value: &str) -> String {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    }
+
+    let mut csv =
+        "time,account,balance_graph,cashflow_graph,block_graph,age_graph,inactive_tax_graph,actual,delegated,max_power,active,error"
+            .to_string();
+    for row in rows {
+        let fields = [
+            row.time.to_string(),
+            quoted(&row.account),
+            field(&row.balance_graph),
+            field(&row.cashflow_graph),
+            field(&row.block_graph),
+            field(&row.age_graph),
+            field(&row.inactive_tax_graph),
+            field(&row.actual),
+            field(&row.delegated),
+            field(&row.max_power),
+            field(&row.active),
+            row.error.as_deref().map(quoted).unwrap_or_default(),
+        ];
+        csv.push('\n');
+        csv.push_str(&fields.join(","));
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_simulate() {
+        let scenario = toml::from_str::<Scenario>(
+            r#"
+            time = { start = 0, end = 5184000, step = 2592000 }
+
+            [[accounts]]
+            name = "main"
+            blocks = [
+                { timestamp = 0, balance = 1000 },
+                { timestamp = 2592000, balance = 800 },
+                { timestamp = 5184000, balance = 900 },
+            ]
+
+            [[accounts]]
+            name = "farm"
+            blocks = [{ timestamp = 2592000, balance = 100, delegate = "main" }]
+            "#,
+        )
+        .expect("should parse scenario");
+
+        let rows = simulate(Config::default(), scenario).await.expect("should simulate");
+        let points = rows.iter().map(|row| (row.time, row.account.as_str())).collect::<Vec<_>>();
+        assert_eq!(
+            points,
+            vec![(0, "main"), (2592000, "main"), (2592000, "farm"), (5184000, "main"), (5184000, "farm")]
+        );
+
+        // the last block of main is compared against its first one
+        let last = &rows[3];
+        assert_eq!(last.cashflow_graph, Some(100.0));
+        assert!(last.actual.is_some());
+
+        // farm delegates its power to main
+        assert_eq!(rows[1].delegated, rows[2].actual);
+        assert_eq!(rows[2].active, Some(0));
+        assert_eq!(rows[1].max_power, None);
+        assert_eq!(to_csv(&rows).lines().count(), rows.len() + 1);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_scenario() {
+        let scenario = toml::from_str::<Scenario>(
+            r#"
+            time = { start = 0, end = 10, step = 1 }
+
+            [[accounts]]
+            name = "main"
+            blocks = [{ timestamp = 0, balance = 1000, delegate = "unknown" }]
+            "#,
+        )
+        .expect("should parse scenario");
+
+        let result = simulate(Config::default(), scenario).await;
+        assert!(matches!(result, Err(CLIError::InvalidScenario(_))));
+    }
+}
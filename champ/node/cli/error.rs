@@ -10,6 +10,8 @@ pub enum CLIError {
     UserExists,
     #[error("please generate a JWT key pair with '$ champ admin generate-key' ")]
     NoKeyPair,
+    #[error("invalid scenario: {0}")]
+    InvalidScenario(String),
     #[error("this command does not exist")]
     UnknownCommand,
 }
@@ -58,7 +58,37 @@ pub fn new() -> clap::ArgMatches {
                                 .min_values(0),
                         ),
                 )
-                .subcommand(clap::Command::new("generate-key").about("generates a node private key used for JWTs")),
+                .subcommand(clap::Command::new("generate-key").about("generates a node private key used for JWTs"))
+                .subcommand(
+                    clap::Command::new("simulate-power")
+                        .about("calculates the voting power of the accounts in a scenario file over time")
+                        .arg(
+                            Arg::new("scenario")
+                                .short('s')
+                                .long("scenario")
+                                .help("scenario file with accounts and their blocks")
+                                .takes_value(true)
+                                .value_name("FILE")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::new("format")
+                                .short('f')
+                                .long("format")
+                                .help("output format")
+                                .takes_value(true)
+                                .possible_values(["csv", "json"])
+                                .default_value("csv"),
+                        )
+                        .arg(
+                            Arg::new("output")
+                                .short('o')
+                                .long("output")
+                                .help("writes the results to a file instead of stdout")
+                                .takes_value(true)
+                                .value_name("FILE"),
+                        ),
+                ),
         )
         .get_matches()
 }
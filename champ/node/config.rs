@@ -1,3 +1,4 @@
+use crate::chain::{ChainError, ConsensusParams, ProtocolParams, VersionActivation, VersionSchedule};
 use crate::storage::{DatabaseConfig, Databases};
 use anyhow::Result;
 use anyhow::{anyhow, Context};
@@ -45,6 +46,10 @@ fn default_consensus() -> ConsensusSettings {
     }
 }
 
+fn default_blockpool() -> BlockpoolSettings {
+    BlockpoolSettings::default()
+}
+
 fn default_wallets() -> WalletManagerConfig {
     WalletManagerConfig::default()
 }
@@ -70,6 +75,9 @@ pub struct Config {
     #[serde(default = "default_consensus")]
     pub consensus: ConsensusSettings,
 
+    #[serde(default = "default_blockpool")]
+    pub blockpool: BlockpoolSettings,
+
     #[serde(skip_serializing)]
     config_path_override: Option<String>,
 
@@ -89,6 +97,42 @@ pub struct ConsensusSettings {
 
     #[serde(with = "ModeDef")]
     pub mode: Mode,
+
+    /// replaces the chain's consensus params, e.g. for testnets, has to be the same on every node of the chain
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<ConsensusParams>,
+
+    /// replaces the chain's block version schedule, has to be the same on every node of the chain
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub versions: Option<Vec<VersionActivation>>,
+}
+
+impl ConsensusSettings {
+    /// Returns the protocol parameters of the configured chain
+    pub fn protocol_params(&self) -> Result<ProtocolParams, ChainError> {
+        ProtocolParams::for_chain(&self.chain)
+    }
+
+    /// Returns the validated consensus params of the configured chain, including overrides from the config
+    pub fn consensus_params(&self) -> Result<ConsensusParams, ChainError> {
+        let chain_params = ConsensusParams::for_chain(&self.chain)?;
+        let params = self.params.clone().unwrap_or(chain_params);
+        params.validate()?;
+        Ok(params)
+    }
+
+    /// Returns the hash identifying the configured chain and its consensus params
+    pub fn chain_identity(&self) -> Result<[u8; 32], ChainError> {
+        Ok(self.consensus_params()?.chain_identity(&self.chain))
+    }
+
+    /// Returns the validated block version schedule of the configured chain, including overrides from the config
+    pub fn version_schedule(&self) -> Result<VersionSchedule, ChainError> {
+        let chain_schedule = VersionSchedule::for_chain(&self.chain)?;
+        let schedule = self.versions.clone().map(VersionSchedule::new).unwrap_or(chain_schedule);
+        schedule.validate()?;
+        Ok(schedule)
+    }
 }
 
 impl Default for ConsensusSettings {
@@ -96,10 +140,46 @@ impl Default for ConsensusSettings {
         Self {
             chain: "dev".to_string(),
             mode: Mode::Validating,
+            params: None,
+            versions: None,
         }
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BlockpoolSettings {
+    /// max number of blocks waiting for confirmation, missing dependencies or a fork vote
+    pub max_blocks: usize,
+    /// max number of blocks a single account can have in the blockpool
+    pub max_blocks_per_account: usize,
+    /// max number of requests waiting to be handled by the blockpool
+    pub channel_size: usize,
+    /// seconds after which blocks that are still not confirmed are dropped
+    pub max_block_age: u64,
+}
+
+impl Default for BlockpoolSettings {
+    fn default() -> Self {
+        Self {
+            max_blocks: 10_000,
+            max_blocks_per_account: 64,
+            channel_size: 1000,
+            max_block_age: 10 * 60,
+        }
+    }
+}
+
+impl BlockpoolSettings {
+    /// Checks that the blockpool can be started with the settings
+    pub fn validate(&self) -> Result<()> {
+        // a channel without capacity can't be created
+        if self.channel_size == 0 {
+            return Err(anyhow!("blockpool.channel_size has to be greater than 0"));
+        }
+        Ok(())
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct UserAccount {
     pub permissions: Vec<String>,
@@ -155,6 +235,7 @@ impl Config {
         let config_path = self.get_path()?;
         let config_file = read_or_create_file(config_path.clone()).with_context(|| "failed to read file")?;
         let config = toml::from_str::<Config>(&config_file).with_context(|| "failed to parse file")?;
+        config.blockpool.validate()?;
         let config_path = config_path.as_path();
 
         // Update config
@@ -162,6 +243,8 @@ impl Config {
         self.database = config.database.clone();
         self.admin = config.admin;
         self.node_users = config.node_users;
+        self.blockpool = config.blockpool;
+        self.consensus = config.consensus;
 
         self.data_path = if let Some(path) = config.database.path {
             let path = path.parse::<PathBuf>()?;
@@ -0,0 +1,500 @@
+//! Heuristics for farming wallets, see `docs/developers/consensus/importance_farming.md`
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Result};
+use encoding::account::generate_account_address;
+use pog_proto::api::{transaction::Data, AccountID, BlockID, SignedBlock, TransactionID};
+use tracing::trace;
+
+use crate::consensus::fixed::Fixed;
+use crate::state::ChampStateArc;
+use crate::storage::{Database, DatabaseError};
+
+const DAY: u64 = 60 * 60 * 24;
+/// Accounts that delegate at this age or later don't count as delegating young
+const YOUNG_DELEGATION_AGE: u64 = 30 * DAY;
+/// Min number of blocks needed to judge how regular an account's activity is
+const MIN_HISTORY_BLOCKS: usize = 4;
+
+/// Signals that an account is a farming wallet, each from 0 (normal wallet) to 100 (typical farming wallet)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FarmingSignals {
+    /// blocks are created at very even intervals
+    pub even_cadence: u8,
+    /// incoming funds come from a single account
+    pub single_funder: u8,
+    /// the account delegated soon after it was created
+    pub young_delegation: u8,
+    /// the account never changed its representative
+    pub static_delegate: u8,
+    /// funds are only exchanged with the representative or accounts delegating to the same representative
+    pub closed_graph: u8,
+}
+
+impl FarmingSignals {
+    /// Combined score from 0 (normal wallet) to 100 (farming wallet), the average of all signals
+    pub fn score(&self) -> u8 {
+        let signals =
+            [self.even_cadence, self.single_funder, self.young_delegation, self.static_delegate, self.closed_graph];
+        (signals.iter().map(|signal| *signal as u32).sum::<u32>() / signals.len() as u32) as u8
+    }
+}
+
+/// How regular the intervals between an account's blocks are, updated block by block
+#[derive(Debug, Clone, Copy, Default)]
+struct Cadence {
+    blocks: usize,
+    last_timestamp: u64,
+    interval_sum: u128,
+    interval_square_sum: u128,
+}
+
+impl Cadence {
+    fn add(&mut self, timestamp: u64) {
+        if self.blocks > 0 {
+            let interval = timestamp.saturating_sub(self.last_timestamp) as u128;
+            self.interval_sum = self.interval_sum.saturating_add(interval);
+            self.interval_square_sum = self.interval_square_sum.saturating_add(interval.saturating_mul(interval));
+        }
+        self.blocks += 1;
+        self.last_timestamp = timestamp;
+    }
+
+    /// Blocks created at fixed intervals score high, based on how much the intervals vary around their mean
+    fn score(&self) -> u8 {
+        if self.blocks < MIN_HISTORY_BLOCKS {
+            return 0;
+        }
+        let intervals = (self.blocks - 1) as i128;
+        let to_i128 = |value: u128| i128::try_from(value).unwrap_or(i128::MAX);
+        let mean = Fixed::from_ratio(to_i128(self.interval_sum), intervals);
+        if mean == Fixed::ZERO {
+            return 0;
+        }
+        let variance = Fixed::from_ratio(to_i128(self.interval_square_sum), intervals) - mean * mean;
+        let variation = variance.max(Fixed::ZERO).sqrt() / mean;
+        (Fixed::from_int(100) * (Fixed::ONE - variation)).to_u32().min(100) as u8
+    }
+}
+
+/// Everything the farming signals of an account are calculated from
+///
+/// Only depends on the account's own blocks, so it is extended with new blocks instead of reading the whole history
+/// again, unless its latest block was rolled back.
+#[derive(Debug, Clone, Default)]
+struct FarmingHistory {
+    /// the latest block that is part of the history and its height
+    latest: Option<(BlockID, u64)>,
+    created_at: u64,
+    cadence: Cadence,
+    incoming: HashMap<AccountID, u64>,
+    outgoing: HashMap<AccountID, u64>,
+    delegations: usize,
+    first_delegation: Option<u64>,
+    representative: Option<AccountID>,
+}
+
+impl FarmingHistory {
+    async fn add_block(&mut self, db: &dyn Database, block: &SignedBlock) -> Result<()> {
+        if self.cadence.blocks == 0 {
+            self.created_at = block.timestamp;
+        }
+        self.cadence.add(block.timestamp);
+
+        for tx in block.data.iter().flat_map(|data| &data.transactions) {
+            match &tx.data {
+                Some(Data::TxSend(send)) => {
+                    let receiver: AccountID =
+                        send.receiver.clone().try_into().map_err(|_| anyhow!("invalid receiver"))?;
+                    *self.outgoing.entry(receiver).or_default() += send.amount;
+                }
+                Some(Data::TxClaim(claim)) => {
+                    let send_id: TransactionID =
+                        claim.send_transaction_id.clone().try_into().map_err(|_| anyhow!("invalid send id"))?;
+                    let amount = match db.get_transaction_by_id(send_id).await?.data {
+                        Some(Data::TxSend(send)) => send.amount,
+                        _ => continue,
+                    };
+                    let send_block = match db.get_transaction_block_id(send_id).await? {
+                        Some(block_id) => db.get_block_by_id(block_id).await?,
+                        None => continue,
+                    };
+                    let sender = generate_account_address(send_block.public_key)?;
+                    *self.incoming.entry(sender).or_default() += amount;
+                }
+                Some(Data::TxDelegate(delegate)) => {
+                    let representative: AccountID =
+                        delegate.representative.clone().try_into().map_err(|_| anyhow!("invalid representative"))?;
+                    self.delegations += 1;
+                    self.first_delegation.get_or_insert(block.timestamp);
+                    self.representative = Some(representative);
+                }
+                None => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Farming history of every account scored so far
+#[derive(Debug, Default)]
+pub struct FarmingCache {
+    accounts: Mutex<HashMap<AccountID, FarmingHistory>>,
+}
+
+impl FarmingCache {
+    fn take(&self, account: &AccountID) -> Option<FarmingHistory> {
+        self.accounts.lock().expect("farming cache lock should not be poisoned").remove(account)
+    }
+
+    fn insert(&self, account: AccountID, history: FarmingHistory) {
+        self.accounts.lock().expect("farming cache lock should not be poisoned").insert(account, history);
+    }
+
+    /// Drops the history of every account, it is read from the database again when it is needed
+    pub fn clear(&self) {
+        self.accounts.lock().expect("farming cache lock should not be poisoned").clear();
+    }
+}
+
+/// Scores an account's stored history on the signals of farming wallets
+pub async fn get_farming_signals(state: &ChampStateArc, account: AccountID) -> Result<FarmingSignals> {
+    let db = state.db.lock().await;
+
+    let latest = match db.get_latest_block_by_account(account).await {
+        Ok(block) => block,
+        Err(DatabaseError::NoLastBlock) => return Ok(FarmingSignals::default()),
+        Err(err) => return Err(err.into()),
+    };
+    let latest_height = latest.data.as_ref().ok_or_else(|| anyhow!("missing block data"))?.height;
+    let latest_id = latest.get_id().map_err(|_| anyhow!("block id could not be created"))?;
+
+    let mut history = state.farming_cache.take(&account).unwrap_or_default();
+    if let Some((block_id, height)) = history.latest {
+        let cached = db.get_block_by_height(account, &height).await?.and_then(|block| block.get_id().ok());
+        if cached != Some(block_id) {
+            history = FarmingHistory::default();
+        }
+    }
+    let next_height = history.latest.map_or(0, |(_, height)| height + 1);
+    for height in next_height..=latest_height {
+        if let Some(block) = db.get_block_by_height(account, &height).await? {
+            history.add_block(&**db, &block).await?;
+        }
+    }
+    history.latest = Some((latest_id, latest_height));
+
+    // the representative and everyone delegating to it
+    let mut circle = HashSet::new();
+    if let Some(representative) = history.representative {
+        circle.insert(representative);
+        circle.extend(db.get_delegates_by_account(representative).await?);
+    }
+    drop(db);
+
+    let mut counterparties = history.incoming.clone();
+    for (account, amount) in &history.outgoing {
+        *counterparties.entry(*account).or_default() += amount;
+    }
+
+    let signals = FarmingSignals {
+        even_cadence: history.cadence.score(),
+        single_funder: single_funder(&history.incoming),
+        young_delegation: young_delegation(history.created_at, history.first_delegation),
+        static_delegate: static_delegate(history.delegations, history.cadence.blocks),
+        closed_graph: closed_graph(&counterparties, &circle),
+    };
+    trace!("farming signals: {:?}", signals);
+    state.farming_cache.insert(account, history);
+    Ok(signals)
+}
+
+/// Reduces the power an account contributes to its representative by its farming score, if the chain is configured to
+pub async fn dampen_delegated_power(state: &ChampStateArc, account: AccountID, power: u32) -> Result<u32> {
+    if !state.config.read().await.consensus.consensus_params()?.dampen_farming {
+        return Ok(power);
+    }
+    let score = get_farming_signals(state, account).await?.score();
+    Ok((power as u64 * (100 - score as u64) / 100) as u32)
+}
+
+/// Share of the received funds that came from the biggest sender
+fn single_funder(incoming: &HashMap<AccountID, u64>) -> u8 {
+    let total: u64 = incoming.values().sum();
+    match incoming.values().max() {
+        Some(max) if total > 0 => (*max as u128 * 100 / total as u128) as u8,
+        _ => 0,
+    }
+}
+
+/// Delegating within the first day scores 100, decreasing until the account is a month old
+fn young_delegation(created_at: u64, first_delegation: Option<u64>) -> u8 {
+    let age = match first_delegation {
+        Some(timestamp) => timestamp.saturating_sub(created_at),
+        None => return 0,
+    };
+    if age <= DAY {
+        return 100;
+    }
+    (YOUNG_DELEGATION_AGE.saturating_sub(age) * 100 / (YOUNG_DELEGATION_AGE - DAY)) as u8
+}
+
+/// Accounts with some history that delegated exactly once
+fn static_delegate(delegations: usize, blocks: usize) -> u8 {
+    if delegations == 1 && blocks >= MIN_HISTORY_BLOCKS {
+        100
+    } else {
+        0
+    }
+}
+
+/// Share of the exchanged funds that stayed within the circle around the account's representative
+fn closed_graph(counterparties: &HashMap<AccountID, u64>, circle: &HashSet<AccountID>) -> u8 {
+    let total: u64 = counterparties.values().sum();
+    if total == 0 {
+        return 0;
+    }
+    let closed: u64 =
+        counterparties.iter().filter(|(account, _)| circle.contains(*account)).map(|(_, amount)| amount).sum();
+    (closed as u128 * 100 / total as u128) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::ConsensusParams;
+    use crate::state::ChampState;
+    use crypto::signatures::ed25519::{create_public_key, create_signature, generate_private_key};
+    use pog_proto::api::signed_block::BlockData;
+    use pog_proto::api::transaction::TxDelegate;
+    use pog_proto::api::Transaction;
+    use prost::Message;
+
+    /// Account that stores its blocks in the database of the state
+    struct TestAccount {
+        private_key: [u8; 32],
+        public_key: Vec<u8>,
+        previous: Vec<u8>,
+        height: u64,
+    }
+
+    impl TestAccount {
+        fn new() -> Self {
+            let private_key = generate_private_key().expect("should generate private key");
+            let public_key = create_public_key(&private_key).expect("should calculate public key").to_vec();
+            Self {
+                private_key,
+                public_key,
+                previous: vec![],
+                height: 0,
+            }
+        }
+
+        fn address(&self) -> AccountID {
+            generate_account_address(self.public_key.clone()).expect("should generate address")
+        }
+
+        fn block(&self, timestamp: u64, transactions: Vec<Transaction>) -> SignedBlock {
+            let data = BlockData {
+                version: 0,
+                signature_type: 0,
+                balance: 1000,
+                height: self.height,
+                previous: self.previous.clone(),
+                transactions,
+            };
+            let signature = create_signature(&data.encode_to_vec(), &self.private_key).expect("should sign block");
+            SignedBlock {
+                signature: signature.to_vec(),
+                public_key: self.public_key.clone(),
+                timestamp,
+                data: Some(data),
+            }
+        }
+
+        async fn add_block(
+            &mut self,
+            state: &ChampStateArc,
+            timestamp: u64,
+            transactions: Vec<Transaction>,
+        ) -> BlockID {
+            let block = self.block(timestamp, transactions);
+            let block_id = block.get_id().expect("should calculate block id");
+            state.db.lock().await.add_block(block).await.expect("should add block");
+            self.previous = block_id.to_vec();
+            self.height += 1;
+            block_id
+        }
+
+        async fn add_blocks(&mut self, state: &ChampStateArc, timestamps: &[u64]) {
+            for timestamp in timestamps {
+                self.add_block(state, *timestamp, vec![]).await;
+            }
+        }
+
+        async fn remove_latest_block(&mut self, state: &ChampStateArc) {
+            let block_id: BlockID = self.previous.clone().try_into().expect("should have a block");
+            let block = state.db.lock().await.get_block_by_id(block_id).await.expect("should get block");
+            state.db.lock().await.remove_block(block_id).await.expect("should remove block");
+            self.previous = block.data.expect("should have data").previous;
+            self.height -= 1;
+        }
+    }
+
+    fn delegate(representative: AccountID) -> Transaction {
+        Transaction {
+            data: Some(Data::TxDelegate(TxDelegate {
+                representative: representative.to_vec(),
+            })),
+        }
+    }
+
+    fn cached_history(state: &ChampStateArc, account: AccountID) -> Option<FarmingHistory> {
+        state.farming_cache.accounts.lock().expect("farming cache lock should not be poisoned").get(&account).cloned()
+    }
+
+    /// Signals calculated from the account's whole history, without the cache
+    async fn uncached_signals(state: &ChampStateArc, account: AccountID) -> FarmingSignals {
+        let history = state.farming_cache.take(&account);
+        let signals = get_farming_signals(state, account).await.expect("should calculate signals");
+        if let Some(history) = history {
+            state.farming_cache.insert(account, history);
+        }
+        signals
+    }
+
+    fn even_cadence(timestamps: &[u64]) -> u8 {
+        let mut cadence = Cadence::default();
+        for timestamp in timestamps {
+            cadence.add(*timestamp);
+        }
+        cadence.score()
+    }
+
+    #[test]
+    fn test_even_cadence() {
+        assert_eq!(even_cadence(&[0, 100, 200]), 0);
+        assert_eq!(even_cadence(&[0, 100, 200, 300, 400]), 100);
+        assert_eq!(even_cadence(&[0, 0, 0, 0]), 0);
+        assert!(even_cadence(&[0, 10, 300, 320, 900]) < 50);
+        assert!(even_cadence(&[0, 100, 210, 300, 400]) > 90);
+    }
+
+    #[test]
+    fn test_funding_signals() {
+        let incoming = HashMap::from([([1; 24], 90), ([2; 24], 10)]);
+        assert_eq!(single_funder(&incoming), 90);
+        assert_eq!(single_funder(&HashMap::new()), 0);
+
+        let circle = HashSet::from([[1; 24]]);
+        assert_eq!(closed_graph(&incoming, &circle), 90);
+    }
+
+    #[test]
+    fn test_delegation_signals() {
+        assert_eq!(young_delegation(0, None), 0);
+        assert_eq!(young_delegation(10, Some(10 + DAY)), 100);
+        assert_eq!(young_delegation(0, Some(YOUNG_DELEGATION_AGE)), 0);
+        assert!(young_delegation(0, Some(10 * DAY)) > 50);
+
+        assert_eq!(static_delegate(1, MIN_HISTORY_BLOCKS), 100);
+        assert_eq!(static_delegate(2, MIN_HISTORY_BLOCKS), 0);
+        assert_eq!(static_delegate(1, 1), 0);
+    }
+
+    #[test]
+    fn test_farming_score() {
+        let signals = FarmingSignals {
+            even_cadence: 100,
+            single_funder: 100,
+            young_delegation: 50,
+            static_delegate: 0,
+            closed_graph: 0,
+        };
+        assert_eq!(signals.score(), 50);
+    }
+
+    #[tokio::test]
+    async fn test_incremental_history() {
+        let state = ChampState::mock().await;
+        let mut account = TestAccount::new();
+        account.add_blocks(&state, &[0, DAY, 2 * DAY]).await;
+
+        let signals = get_farming_signals(&state, account.address()).await.expect("should calculate signals");
+        assert_eq!(signals.even_cadence, 0);
+        let history = cached_history(&state, account.address()).expect("should cache history");
+        assert_eq!(history.cadence.blocks, 3);
+
+        // only the new blocks are added to the cached history
+        account.add_blocks(&state, &[3 * DAY, 4 * DAY]).await;
+        let signals = get_farming_signals(&state, account.address()).await.expect("should calculate signals");
+        assert_eq!(signals.even_cadence, 100);
+        let history = cached_history(&state, account.address()).expect("should cache history");
+        assert_eq!(history.cadence.blocks, 5);
+        assert_eq!(history.latest.map(|(_, height)| height), Some(4));
+        assert_eq!(signals, uncached_signals(&state, account.address()).await);
+
+        // nothing changes without new blocks
+        let again = get_farming_signals(&state, account.address()).await.expect("should calculate signals");
+        assert_eq!(again, signals);
+        assert_eq!(cached_history(&state, account.address()).expect("should cache history").cadence.blocks, 5);
+    }
+
+    #[tokio::test]
+    async fn test_history_reset_on_rollback() {
+        let state = ChampState::mock().await;
+        let representative = TestAccount::new().address();
+        let mut account = TestAccount::new();
+        account.add_blocks(&state, &[0, DAY, 2 * DAY, 3 * DAY]).await;
+        account.add_block(&state, 4 * DAY, vec![delegate(representative)]).await;
+
+        let signals = get_farming_signals(&state, account.address()).await.expect("should calculate signals");
+        assert_eq!(signals.even_cadence, 100);
+        assert_eq!(signals.static_delegate, 100);
+
+        // the latest block is replaced by a block at the same height that doesn't delegate
+        account.remove_latest_block(&state).await;
+        let replaced = account.add_block(&state, 4 * DAY + 10, vec![]).await;
+
+        let signals = get_farming_signals(&state, account.address()).await.expect("should calculate signals");
+        assert_eq!(signals.static_delegate, 0);
+        assert_eq!(signals.young_delegation, 0);
+        let history = cached_history(&state, account.address()).expect("should cache history");
+        assert_eq!(history.latest, Some((replaced, 4)));
+        assert_eq!(history.cadence.blocks, 5);
+        assert_eq!(history.delegations, 0);
+        assert_eq!(signals, uncached_signals(&state, account.address()).await);
+    }
+
+    #[tokio::test]
+    async fn test_dampen_delegated_power() {
+        let state = ChampState::mock().await;
+        let representative = TestAccount::new().address();
+        // delegates in its first block and creates blocks every day
+        let mut farming = TestAccount::new();
+        farming.add_block(&state, 0, vec![delegate(representative)]).await;
+        farming.add_blocks(&state, &[DAY, 2 * DAY, 3 * DAY, 4 * DAY]).await;
+        let mut unused = TestAccount::new();
+        unused.add_block(&state, 0, vec![]).await;
+
+        let score = get_farming_signals(&state, farming.address()).await.expect("should calculate signals").score();
+        assert_eq!(score, 60);
+
+        // the chain doesn't dampen farming by default
+        let power = dampen_delegated_power(&state, farming.address(), 1000).await.expect("should dampen power");
+        assert_eq!(power, 1000);
+
+        let chain = state.config.read().await.consensus.chain.clone();
+        let params = ConsensusParams::for_chain(&chain).expect("should have params");
+        state.config.write().await.consensus.params = Some(ConsensusParams {
+            dampen_farming: true,
+            ..params
+        });
+        let power = dampen_delegated_power(&state, farming.address(), 1000).await.expect("should dampen power");
+        assert_eq!(power, 400);
+        let power = dampen_delegated_power(&state, unused.address(), 1000).await.expect("should dampen power");
+        assert_eq!(power, 1000);
+    }
+}
@@ -0,0 +1,288 @@
+//! Deterministic fixed-point arithmetic for voting power
+//!
+//! Floating point results of functions like `log10` or `powi` can differ between platforms and compilers, but every
+//! node has to calculate exactly the same voting power. [`Fixed`] stores numbers with 12 decimal places in an `i128`
+//! and only uses integer operations.
+//!
+//! Rounding:
+//! - multiplication and division truncate toward zero, like integer division
+//! - `sqrt` and `log10` round down
+//! - all operations saturate at the bounds of `i128` instead of overflowing, dividing by zero saturates as well
+
+use std::fmt;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+pub const DECIMALS: u32 = 12;
+const SCALE: i128 = 10_i128.pow(DECIMALS);
+// sqrt(SCALE), used when a square root would overflow otherwise
+const SQRT_SCALE: u128 = 1_000_000;
+// number of binary digits calculated for the fractional part of log10
+const LOG_BITS: u32 = 40;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fixed(i128);
+
+impl Fixed {
+    pub const ZERO: Fixed = Fixed(0);
+    pub const ONE: Fixed = Fixed(SCALE);
+    pub const MAX: Fixed = Fixed(i128::MAX);
+    pub const MIN: Fixed = Fixed(i128::MIN);
+
+    pub fn from_int(value: impl Into<i128>) -> Self {
+        Fixed(value.into().saturating_mul(SCALE))
+    }
+
+    /// Returns `numerator / denominator`, truncated toward zero
+    pub fn from_ratio(numerator: impl Into<i128>, denominator: impl Into<i128>) -> Self {
+        Fixed::from_int(numerator) / Fixed::from_int(denominator)
+    }
+
+    /// Converts a configured parameter, rounded to the nearest representable number
+    ///
+    /// This only uses a multiplication and rounding, which IEEE 754 requires to give the same result on every
+    /// platform. NaN converts to 0.
+    pub fn from_f64(value: f64) -> Self {
+        Fixed((value * SCALE as f64).round() as i128)
+    }
+
+    /// Lossy conversion for displaying values, never use the result for consensus
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / SCALE as f64
+    }
+
+    /// Integer part, truncated toward zero
+    pub fn trunc(self) -> i128 {
+        self.0 / SCALE
+    }
+
+    /// Integer part truncated toward zero, negative numbers become 0 and large ones `u32::MAX`
+    pub fn to_u32(self) -> u32 {
+        self.trunc().clamp(0, u32::MAX as i128) as u32
+    }
+
+    pub fn powi(self, exp: u32) -> Self {
+        let mut result = Fixed::ONE;
+        let mut base = self;
+        let mut exp = exp;
+        while exp > 0 {
+            if exp & 1 == 1 {
+                result = result * base;
+            }
+            exp >>= 1;
+            if exp > 0 {
+                base = base * base;
+            }
+        }
+        result
+    }
+
+    /// Square root rounded down, 0 for negative numbers
+    pub fn sqrt(self) -> Self {
+        if self.0 <= 0 {
+            return Fixed::ZERO;
+        }
+        let value = self.0 as u128;
+        let root = match value.checked_mul(SCALE as u128) {
+            Some(scaled) => isqrt(scaled),
+            // loses the lower decimal places, only happens for numbers above ~10^26
+            None => isqrt(value) * SQRT_SCALE,
+        };
+        Fixed(root as i128)
+    }
+
+    /// Base 10 logarithm, `Fixed::MIN` for numbers that are not positive
+    ///
+    /// The integer part is the number of times the value can be divided by 10, the fractional part is calculated
+    /// bit by bit: squaring the remaining value doubles its logarithm, so every time the square reaches 10 the next
+    /// binary digit is 1. The result is rounded down to `LOG_BITS` binary digits.
+    pub fn log10(self) -> Self {
+        if self.0 <= 0 {
+            return Fixed::MIN;
+        }
+        let ten = Fixed::from_int(10);
+
+        let mut integer: i128 = 0;
+        let mut value = self;
+        while value >= ten {
+            value = value / ten;
+            integer += 1;
+        }
+        while value < Fixed::ONE {
+            value = value * ten;
+            integer -= 1;
+        }
+
+        let mut fraction: u64 = 0;
+        for _ in 0..LOG_BITS {
+            value = value * value;
+            fraction <<= 1;
+            if value >= ten {
+                value = value / ten;
+                fraction |= 1;
+            }
+        }
+
+        Fixed::from_int(integer) + Fixed(((fraction as i128) * SCALE) >> LOG_BITS)
+    }
+}
+
+/// Integer square root, rounded down
+fn isqrt(value: u128) -> u128 {
+    if value < 2 {
+        return value;
+    }
+    // start above the root, Newton's method then decreases until it reaches it
+    let bits = 128 - value.leading_zeros();
+    let mut root = 1_u128 << ((bits + 1) / 2);
+    loop {
+        let next = (root + value / root) / 2;
+        if next >= root {
+            return root;
+        }
+        root = next;
+    }
+}
+
+impl Add for Fixed {
+    type Output = Fixed;
+
+    fn add(self, rhs: Fixed) -> Fixed {
+        Fixed(self.0.saturating_add(rhs.0))
+    }
+}
+
+impl Sub for Fixed {
+    type Output = Fixed;
+
+    fn sub(self, rhs: Fixed) -> Fixed {
+        Fixed(self.0.saturating_sub(rhs.0))
+    }
+}
+
+impl Neg for Fixed {
+    type Output = Fixed;
+
+    fn neg(self) -> Fixed {
+        Fixed(self.0.saturating_neg())
+    }
+}
+
+impl Mul for Fixed {
+    type Output = Fixed;
+
+    fn mul(self, rhs: Fixed) -> Fixed {
+        match self.0.checked_mul(rhs.0) {
+            Some(product) => Fixed(product / SCALE),
+            None => {
+                // multiply the integer and fractional part separately, both are truncated toward zero
+                let integer = (self.0 / SCALE).saturating_mul(rhs.0);
+                let fraction = self.0 % SCALE;
+                let fraction = match fraction.checked_mul(rhs.0) {
+                    Some(product) => product / SCALE,
+                    None => (rhs.0 / SCALE).saturating_mul(fraction),
+                };
+                Fixed(integer.saturating_add(fraction))
+            }
+        }
+    }
+}
+
+impl Div for Fixed {
+    type Output = Fixed;
+
+    fn div(self, rhs: Fixed) -> Fixed {
+        if rhs.0 == 0 {
+            return match self.0.signum() {
+                1 => Fixed::MAX,
+                -1 => Fixed::MIN,
+                _ => Fixed::ZERO,
+            };
+        }
+        match self.0.checked_mul(SCALE) {
+            Some(scaled) => Fixed(scaled / rhs.0),
+            None => {
+                // divide the quotient and the remainder separately, both are truncated toward zero
+                let integer = self.0.saturating_div(rhs.0).saturating_mul(SCALE);
+                let remainder = self.0.checked_rem(rhs.0).unwrap_or(0);
+                let fraction = match remainder.checked_mul(SCALE) {
+                    Some(scaled) => scaled / rhs.0,
+                    None => remainder / (rhs.0 / SCALE),
+                };
+                Fixed(integer.saturating_add(fraction))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Fixed {
+    /// Prints the number with up to 12 decimal places, without trailing zeros
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 {
+            "-"
+        } else {
+            ""
+        };
+        let abs = self.0.unsigned_abs();
+        let integer = abs / SCALE as u128;
+        let fraction = abs % SCALE as u128;
+        if fraction == 0 {
+            return write!(f, "{sign}{integer}");
+        }
+        let fraction = format!("{:0width$}", fraction, width = DECIMALS as usize);
+        write!(f, "{sign}{integer}.{}", fraction.trim_end_matches('0'))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rounding() {
+        assert_eq!(Fixed::from_ratio(1, 3).to_string(), "0.333333333333");
+        assert_eq!(Fixed::from_ratio(-2, 3).to_string(), "-0.666666666666");
+        assert_eq!(Fixed::from_ratio(-2, 3) * Fixed::from_int(3), Fixed(-1_999_999_999_998));
+        assert_eq!(Fixed::from_f64(0.1), Fixed::from_ratio(1, 10));
+        assert_eq!(Fixed::from_f64(-2.5).trunc(), -2);
+        assert_eq!(Fixed::from_f64(-2.5).to_u32(), 0);
+        assert_eq!(Fixed::from_int(u64::MAX).to_u32(), u32::MAX);
+    }
+
+    #[test]
+    fn test_saturation() {
+        let large = Fixed::from_int(u64::MAX);
+        assert_eq!(large * large * large, Fixed::MAX);
+        assert_eq!(Fixed::ONE / Fixed::ZERO, Fixed::MAX);
+        // doesn't overflow while the result fits
+        assert_eq!(
+            Fixed::from_int(u64::MAX) * Fixed::from_int(1_000_000),
+            Fixed::from_int(u64::MAX as i128 * 1_000_000)
+        );
+        assert_eq!((large * Fixed::from_int(1_000_000)) / Fixed::from_int(1_000_000), large);
+    }
+
+    #[test]
+    fn test_powi() {
+        assert_eq!(Fixed::from_int(2).powi(10), Fixed::from_int(1024));
+        assert_eq!(Fixed::from_int(-3).powi(3), Fixed::from_int(-27));
+        assert_eq!(Fixed::from_int(5).powi(0), Fixed::ONE);
+    }
+
+    #[test]
+    fn test_sqrt() {
+        assert_eq!(Fixed::from_int(16).sqrt(), Fixed::from_int(4));
+        assert_eq!(Fixed::from_int(2).sqrt().to_string(), "1.414213562373");
+        assert_eq!(Fixed::from_int(-4).sqrt(), Fixed::ZERO);
+        assert_eq!(Fixed::from_int(10_i128.pow(20)).sqrt(), Fixed::from_int(10_i128.pow(10)));
+    }
+
+    #[test]
+    fn test_log10() {
+        assert_eq!(Fixed::from_int(1).log10(), Fixed::ZERO);
+        assert_eq!(Fixed::from_int(1000).log10(), Fixed::from_int(3));
+        assert_eq!(Fixed::from_ratio(1, 100).log10(), Fixed::from_int(-2));
+        assert!((Fixed::from_int(2).log10().to_f64() - 2_f64.log10()).abs() < 1e-10);
+        assert!((Fixed::from_int(166).log10().to_f64() - 166_f64.log10()).abs() < 1e-10);
+        assert_eq!(Fixed::ZERO.log10(), Fixed::MIN);
+    }
+}
@@ -1,33 +1,33 @@
-use pog_proto::api::SignedBlock;
+//! Curves the actual voting power of an account is made of
+//!
+//! All curves use [`Fixed`] arithmetic, so every node calculates exactly the same voting power. Intermediate results
+//! are truncated toward zero, see [`crate::consensus::fixed`] for the rounding of each operation.
 
-// so we can normalize the curves
-const TX_CURVE_MAX: i32 = 15;
-const PLATEAU_SIZE: f64 = 175.0;
-// 0.1 = 10%
-const INACTIVE_TAX_PERCENTAGE: f64 = 0.1;
-const INACTIVE_TAX_BOUND: f64 = 0.1;
+use pog_proto::api::SignedBlock;
 
-const NORMALIZE_BALANCE: f64 = 1.0;
-const NORMALIZE_CASHFLOW: f64 = 1.0;
-const NORMALIZE_INACTIVE_TAX: f64 = 1.0;
-const NORMALIZE_BLOCK: f64 = 10.0;
-const NORMALIZE_AGE: f64 = 1.0;
+use crate::chain::ConsensusParams;
+use crate::consensus::fixed::Fixed;
 
-const WEEK_IN_SECONDS: f64 = 60.0 * 60.0 * 24.0 * 7.0;
+const WEEK_IN_SECONDS: u64 = 60 * 60 * 24 * 7;
 
-pub fn balance_graph(balance: u64) -> f64 {
-    balance as f64 / NORMALIZE_BALANCE
+pub fn balance_graph(params: &ConsensusParams, balance: u64) -> Fixed {
+    Fixed::from_int(balance) / Fixed::from_f64(params.normalize_balance)
 }
 
-pub fn cashflow_graph(new_block_balance: u64, old_block_balance: u64) -> f64 {
+pub fn cashflow_graph(params: &ConsensusParams, new_block_balance: u64, old_block_balance: u64) -> Fixed {
     let cashflow = new_block_balance as i128 - old_block_balance as i128;
 
-    -cashflow as f64 / NORMALIZE_CASHFLOW
+    Fixed::from_int(-cashflow) / Fixed::from_f64(params.normalize_cashflow)
 }
 
-pub fn inactive_tax_graph(new_block_balance: u64, old_block_balance: u64, net_importance: f64) -> f64 {
+pub fn inactive_tax_graph(
+    params: &ConsensusParams,
+    new_block_balance: u64,
+    old_block_balance: u64,
+    net_importance: Fixed,
+) -> Fixed {
     let cashflow = new_block_balance as i128 - old_block_balance as i128;
-    let percentage_buffer = (new_block_balance as f64 * INACTIVE_TAX_BOUND) as i128;
+    let percentage_buffer = (Fixed::from_int(new_block_balance) * Fixed::from_f64(params.inactive_tax_bound)).trunc();
 
     let upperbound = percentage_buffer;
     let lowerbound = -percentage_buffer;
@@ -35,43 +35,54 @@ pub fn inactive_tax_graph(new_block_balance: u64, old_block_balance: u64, net_im
     tracing::trace!("real cashflow={}", cashflow);
     // Inactive Tax
     if cashflow < upperbound && cashflow > lowerbound && new_block_balance > 0 {
-        return -(net_importance * INACTIVE_TAX_PERCENTAGE) * NORMALIZE_INACTIVE_TAX;
+        return -(net_importance * Fixed::from_f64(params.inactive_tax_percentage))
+            * Fixed::from_f64(params.normalize_inactive_tax);
     }
-    0.0
+    Fixed::ZERO
 }
 
-pub fn block_graph(block_height: u64, new_block: &SignedBlock, old_block: Option<&SignedBlock>) -> f64 {
+pub fn block_graph(
+    params: &ConsensusParams,
+    block_height: u64,
+    new_block: &SignedBlock,
+    old_block: Option<&SignedBlock>,
+) -> Fixed {
     let old_block_time = match old_block {
         Some(b) => b.timestamp,
         None => new_block.timestamp,
     };
     // to get the time between the first and most recent block
     // we need the minimum to not give too high power from the start
-    let time = if new_block.timestamp - old_block_time < WEEK_IN_SECONDS as u64 {
-        WEEK_IN_SECONDS
-    } else {
-        (new_block.timestamp - old_block_time) as f64
-    };
+    let time = new_block.timestamp.saturating_sub(old_block_time).max(WEEK_IN_SECONDS);
+
+    // without blocks the curve is at its minimum of 1
+    if block_height == 0 {
+        return Fixed::from_f64(params.normalize_block);
+    }
 
     // x is the nr of tx based on the account life in weeks
     // https://www.geogebra.org/calculator/ymkv5ew6
-    let blocks_per_week = (time / block_height as f64) / WEEK_IN_SECONDS;
+    let blocks_per_week = Fixed::from_ratio(time, block_height as i128 * WEEK_IN_SECONDS as i128);
     // this is between 0 and 1 where plateau starts at 0.5
-    let graph_result = 1.0 / (blocks_per_week / PLATEAU_SIZE - 1.0).powi(2 * TX_CURVE_MAX) + 1.0;
+    let distance = blocks_per_week / Fixed::from_f64(params.plateau_size) - Fixed::ONE;
+    // grows without limit close to the plateau, dividing by 0 saturates
+    let graph_result = Fixed::ONE / distance.powi(2 * params.tx_curve_max) + Fixed::ONE;
     // to normalize tx graph and balance graph
-    graph_result * NORMALIZE_BLOCK
+    graph_result * Fixed::from_f64(params.normalize_block)
 }
 
-pub fn age_graph(account_age: u64) -> f64 {
+pub fn age_graph(params: &ConsensusParams, account_age: u64) -> Fixed {
     // x is the account age in weeks
     // starts with negative power but increases at around 1 month
     // slowly increases steadily
     // x + 1 to avoid log0
     // 0.1x + 3 to allow the graph to go through 31 (month ish)
     // - 4 to shift the start
-    let account_age_weeks = (account_age as f64 / WEEK_IN_SECONDS).floor();
-    let graph_result = (account_age_weeks + 1.0).log10() + (0.1 * account_age_weeks + 3.0).sqrt() - 4.0;
-    graph_result * NORMALIZE_AGE
+    let account_age_weeks = account_age / WEEK_IN_SECONDS;
+    let graph_result = Fixed::from_int(account_age_weeks + 1).log10()
+        + (Fixed::from_ratio(account_age_weeks, 10) + Fixed::from_int(3)).sqrt()
+        - Fixed::from_int(4);
+    graph_result * Fixed::from_f64(params.normalize_age)
 }
 
 #[cfg(test)]
@@ -79,26 +90,48 @@ mod tests {
     use insta::assert_yaml_snapshot;
     use pog_proto::api::{signed_block::BlockData, SignedBlock};
 
+    use crate::chain::ConsensusParams;
+    use crate::consensus::fixed::Fixed;
     use crate::consensus::graphs::{age_graph, balance_graph, block_graph, cashflow_graph, inactive_tax_graph};
 
+    // values recorded by the floating point implementation
+    fn float_snapshot(snapshot: &str) -> Vec<f64> {
+        snapshot
+            .lines()
+            .filter_map(|line| line.strip_prefix("- "))
+            .map(|value| value.trim_matches('"').parse().expect("snapshot should contain numbers"))
+            .collect()
+    }
+
+    fn assert_close(results: Vec<Fixed>, expected: Vec<f64>) {
+        assert_eq!(results.len(), expected.len());
+        for (result, expected) in results.into_iter().zip(expected) {
+            assert!((result.to_f64() - expected).abs() < 1e-9, "{result} differs from {expected}");
+        }
+    }
+
     #[test]
     fn test_balance_graph() {
-        assert_eq!(1000.0, balance_graph(1000));
-        assert_eq!(5.0, balance_graph(5));
+        let params = ConsensusParams::default();
+        assert_eq!(Fixed::from_int(1000), balance_graph(&params, 1000));
+        assert_eq!(Fixed::from_int(5), balance_graph(&params, 5));
     }
     #[test]
     fn test_cashflow_graph() {
-        assert_eq!(500.0, cashflow_graph(500, 1000));
-        assert_eq!(-500.0, cashflow_graph(1000, 500));
-        assert_eq!(0.0, cashflow_graph(1000, 1000));
+        let params = ConsensusParams::default();
+        assert_eq!(Fixed::from_int(500), cashflow_graph(&params, 500, 1000));
+        assert_eq!(Fixed::from_int(-500), cashflow_graph(&params, 1000, 500));
+        assert_eq!(Fixed::ZERO, cashflow_graph(&params, 1000, 1000));
     }
     #[test]
     fn test_inactive_tax_graph() {
-        assert_eq!(0.0, inactive_tax_graph(500, 1000, 1000.0));
-        assert_eq!(-50.0, inactive_tax_graph(1000, 1000, 500.0));
+        let params = ConsensusParams::default();
+        assert_eq!(Fixed::ZERO, inactive_tax_graph(&params, 500, 1000, Fixed::from_int(1000)));
+        assert_eq!(Fixed::from_int(-50), inactive_tax_graph(&params, 1000, 1000, Fixed::from_int(500)));
     }
     #[test]
     fn test_block_graph() {
+        let params = ConsensusParams::default();
         let new_block = SignedBlock {
             signature: b"signature".to_vec(),
             public_key: b"public_key".to_vec(),
@@ -126,38 +159,77 @@ mod tests {
             }),
         });
         assert_eq!(
-            (20.17295623738592 * 100_000_f64) as u64,
-            (block_graph(10, &new_block, old_block.as_ref()) * 100_000_f64) as u64
+            2_017_295,
+            (block_graph(&params, 10, &new_block, old_block.as_ref()) * Fixed::from_int(100_000)).trunc()
         );
+        // a lone block is compared against itself
+        assert_eq!(Fixed::from_int(10), block_graph(&params, 0, &new_block, None));
     }
     #[test]
     fn test_age_graph() {
-        assert_eq!((-2.267949192431123 * 100_000_f64) as u64, (age_graph(100_000) * 100_000_f64) as u64);
-        assert_eq!((2.635988521203979 * 100_000_f64) as u64, (age_graph(100_000_000) * 100_000_f64) as u64);
+        let params = ConsensusParams::default();
+        assert_eq!(-226_794, (age_graph(&params, 100_000) * Fixed::from_int(100_000)).trunc());
+        assert_eq!(263_598, (age_graph(&params, 100_000_000) * Fixed::from_int(100_000)).trunc());
     }
     #[test]
     fn test_snapshots() {
+        let params = ConsensusParams::default();
         assert_yaml_snapshot!(vec![
-            balance_graph(1000).to_string(),
-            balance_graph(0).to_string(),
-            balance_graph(2500).to_string()
+            balance_graph(&params, 1000).to_string(),
+            balance_graph(&params, 0).to_string(),
+            balance_graph(&params, 2500).to_string()
         ]);
         assert_yaml_snapshot!(vec![
-            cashflow_graph(500, 1000).to_string(),
-            cashflow_graph(1000, 1000).to_string(),
-            cashflow_graph(0, 0).to_string()
+            cashflow_graph(&params, 500, 1000).to_string(),
+            cashflow_graph(&params, 1000, 1000).to_string(),
+            cashflow_graph(&params, 0, 0).to_string()
         ]);
         assert_yaml_snapshot!(vec![
-            inactive_tax_graph(1000, 1000, 500.0).to_string(),
-            inactive_tax_graph(0, 0, 0.0).to_string(),
-            inactive_tax_graph(1000, 1500, 1000.0).to_string(),
-            inactive_tax_graph(1000, 1100, 1000.0).to_string(),
-            inactive_tax_graph(1000, 1009, 1000.0).to_string()
+            inactive_tax_graph(&params, 1000, 1000, Fixed::from_int(500)).to_string(),
+            inactive_tax_graph(&params, 0, 0, Fixed::ZERO).to_string(),
+            inactive_tax_graph(&params, 1000, 1500, Fixed::from_int(1000)).to_string(),
+            inactive_tax_graph(&params, 1000, 1100, Fixed::from_int(1000)).to_string(),
+            inactive_tax_graph(&params, 1000, 1009, Fixed::from_int(1000)).to_string()
         ]);
         assert_yaml_snapshot!(vec![
-            age_graph(605000).to_string(),
-            age_graph(100_000_000).to_string(),
-            age_graph(0).to_string()
+            age_graph(&params, 605000).to_string(),
+            age_graph(&params, 100_000_000).to_string(),
+            age_graph(&params, 0).to_string()
         ]);
     }
+    #[test]
+    fn test_float_snapshots() {
+        let params = ConsensusParams::default();
+        assert_close(
+            vec![balance_graph(&params, 1000), balance_graph(&params, 0), balance_graph(&params, 2500)],
+            float_snapshot(include_str!("snapshots/champ_node__consensus__graphs__tests__snapshots.snap")),
+        );
+        assert_close(
+            vec![
+                cashflow_graph(&params, 500, 1000),
+                cashflow_graph(&params, 1000, 1000),
+                cashflow_graph(&params, 0, 0),
+            ],
+            float_snapshot(include_str!("snapshots/champ_node__consensus__graphs__tests__snapshots-2.snap")),
+        );
+        assert_close(
+            vec![
+                inactive_tax_graph(&params, 1000, 1000, Fixed::from_int(500)),
+                inactive_tax_graph(&params, 0, 0, Fixed::ZERO),
+                inactive_tax_graph(&params, 1000, 1500, Fixed::from_int(1000)),
+                inactive_tax_graph(&params, 1000, 1100, Fixed::from_int(1000)),
+                inactive_tax_graph(&params, 1000, 1009, Fixed::from_int(1000)),
+            ],
+            float_snapshot(include_str!("snapshots/champ_node__consensus__graphs__tests__snapshots-3.snap")),
+        );
+    }
+    #[test]
+    fn test_age_graph_float() {
+        let params = ConsensusParams::default();
+        // results of the floating point implementation, the fixed-point results only differ in the last digits
+        assert_close(
+            vec![age_graph(&params, 605000), age_graph(&params, 100_000_000), age_graph(&params, 0)],
+            vec![-1.9382883181701178, 2.635988521203979, -2.267949192431123],
+        );
+    }
 }
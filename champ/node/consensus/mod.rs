@@ -1,2 +1,7 @@
+pub mod farming;
+pub mod fixed;
 pub mod graphs;
+pub mod prime_delegates;
+pub mod snapshot;
+pub mod vote;
 pub mod voting_power;
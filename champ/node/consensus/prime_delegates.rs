@@ -0,0 +1,217 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use pog_proto::api::AccountID;
+use prost::Message;
+use tracing::{debug, error, info};
+
+use crate::consensus::snapshot::{take_snapshot, PowerSnapshot};
+use crate::shutdown::Shutdown;
+use crate::state::ChampStateArc;
+
+/// Length of an epoch in seconds, prime delegates are selected again at the start of every epoch
+pub const EPOCH_LENGTH: u64 = 60 * 60 * 24;
+/// Max number of accounts that are prime delegates at the same time
+pub const MAX_PRIME_DELEGATES: usize = 100;
+
+/// How often the node checks whether a new epoch started
+pub const UPDATE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Clone, PartialEq, Message)]
+pub struct PrimeDelegate {
+    #[prost(bytes = "vec", tag = "1")]
+    pub account: Vec<u8>,
+    /// active voting power in the epoch's snapshot
+    #[prost(uint32, tag = "2")]
+    pub power: u32,
+}
+
+/// The prime delegates of an epoch, sorted by voting power
+#[derive(Clone, PartialEq, Message)]
+pub struct PrimeDelegates {
+    #[prost(uint64, tag = "1")]
+    pub epoch: u64,
+    #[prost(message, repeated, tag = "2")]
+    pub delegates: Vec<PrimeDelegate>,
+    /// max active voting power of a single account during the epoch
+    #[prost(uint32, tag = "3")]
+    pub max_power: u32,
+}
+
+impl PrimeDelegates {
+    /// Combined voting power of all prime delegates
+    pub fn total_power(&self) -> u64 {
+        self.delegates.iter().map(|delegate| delegate.power as u64).sum()
+    }
+
+    /// Returns the voting power of an account if it is a prime delegate
+    pub fn get_power(&self, account: &AccountID) -> Option<u32> {
+        self.delegates.iter().find(|delegate| delegate.account == account).map(|delegate| delegate.power)
+    }
+}
+
+pub fn epoch_at(timestamp: u64) -> u64 {
+    timestamp / EPOCH_LENGTH
+}
+
+pub fn current_epoch() -> u64 {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    epoch_at(now.as_secs())
+}
+
+/// Returns the prime delegates of the current epoch, `None` until they were selected for the first time
+pub async fn get_prime_delegates(state: &ChampStateArc) -> Result<Option<PrimeDelegates>> {
+    Ok(state.db.lock().await.get_prime_delegates().await?)
+}
+
+/// Sorts accounts by voting power and keeps the ones that qualify as prime delegates
+///
+/// Accounts with the same power are sorted by their address so every node selects the same accounts.
+pub fn rank_accounts(powers: impl Iterator<Item = (AccountID, u64)>) -> Vec<(AccountID, u64)> {
+    let mut ranked = powers.filter(|(_, power)| *power > 0).collect::<Vec<_>>();
+    ranked.sort_by(|(a, a_power), (b, b_power)| b_power.cmp(a_power).then(a.cmp(b)));
+    ranked.truncate(MAX_PRIME_DELEGATES);
+    ranked
+}
+
+/// Selects the accounts with the most active voting power in an epoch's snapshot as prime delegates
+pub fn select_prime_delegates(snapshot: &PowerSnapshot) -> Result<PrimeDelegates> {
+    let mut powers = Vec::with_capacity(snapshot.accounts.len());
+    for power in &snapshot.accounts {
+        let account: AccountID =
+            power.account.clone().try_into().map_err(|_| anyhow!("snapshot contains an invalid account"))?;
+        powers.push((account, power.active as u64));
+    }
+
+    Ok(PrimeDelegates {
+        epoch: snapshot.epoch,
+        delegates: rank_accounts(powers.into_iter())
+            .into_iter()
+            .map(|(account, power)| PrimeDelegate {
+                account: account.to_vec(),
+                power: power as u32,
+            })
+            .collect(),
+        max_power: snapshot.max_power,
+    })
+}
+
+/// Selects the prime delegates again whenever a new epoch starts, until the node shuts down
+pub async fn start(state: ChampStateArc, shutdown: Shutdown) -> Result<()> {
+    let mut interval = tokio::time::interval(UPDATE_INTERVAL);
+    let shutdown = shutdown.recv();
+    tokio::pin!(shutdown);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                if let Err(err) = update_prime_delegates(&state, current_epoch()).await {
+                    error!("prime delegates could not be selected: {err}");
+                }
+            }
+            _ = &mut shutdown => return Ok(()),
+        }
+    }
+}
+
+/// Takes a snapshot of the voting power of every account and selects the prime delegates of a new epoch
+async fn update_prime_delegates(state: &ChampStateArc, epoch: u64) -> Result<()> {
+    if let Some(current) = get_prime_delegates(state).await? {
+        if current.epoch >= epoch {
+            return Ok(());
+        }
+    }
+
+    debug!("taking voting power snapshot for epoch {epoch}");
+    let snapshot = take_snapshot(state, epoch).await?;
+    let prime_delegates = select_prime_delegates(&snapshot)?;
+    info!("selected {} prime delegates for epoch {epoch}", prime_delegates.delegates.len());
+
+    let mut db = state.db.lock().await;
+    db.set_power_snapshot(snapshot).await?;
+    db.set_prime_delegates(prime_delegates).await?;
+    // histories of accounts that stopped delegating would stay in memory forever otherwise
+    state.farming_cache.clear();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::snapshot::AccountPower;
+    use crate::state::ChampState;
+
+    #[test]
+    fn test_prime_delegates_power() {
+        let prime_delegates = PrimeDelegates {
+            epoch: 1,
+            delegates: vec![
+                PrimeDelegate {
+                    account: vec![1; 24],
+                    power: 30,
+                },
+                PrimeDelegate {
+                    account: vec![2; 24],
+                    power: 20,
+                },
+            ],
+            max_power: 30,
+        };
+        assert_eq!(prime_delegates.total_power(), 50);
+        assert_eq!(prime_delegates.get_power(&[2; 24]), Some(20));
+        assert_eq!(prime_delegates.get_power(&[3; 24]), None);
+        assert_eq!(epoch_at(EPOCH_LENGTH * 3 + 1), 3);
+    }
+
+    #[test]
+    fn test_select_prime_delegates() {
+        let power = |account, active| AccountPower {
+            account: vec![account; 24],
+            actual: 0,
+            active,
+        };
+        let snapshot = PowerSnapshot {
+            epoch: 2,
+            accounts: vec![power(1, 20), power(2, 0), power(3, 30), power(4, 20)],
+            max_power: 30,
+        };
+
+        let prime_delegates = select_prime_delegates(&snapshot).expect("should select prime delegates");
+        assert_eq!(prime_delegates.epoch, 2);
+        // accounts without power are never selected, ties are sorted by address
+        let accounts = prime_delegates.delegates.iter().map(|delegate| delegate.account[0]).collect::<Vec<_>>();
+        assert_eq!(accounts, vec![3, 1, 4]);
+    }
+
+    #[tokio::test]
+    async fn test_update_prime_delegates() {
+        let state = ChampState::mock().await;
+        assert!(get_prime_delegates(&state).await.expect("should get prime delegates").is_none());
+
+        update_prime_delegates(&state, 5).await.expect("should select prime delegates");
+        let snapshot = state.db.lock().await.get_power_snapshot(5).await.expect("should get snapshot");
+        assert!(snapshot.is_some());
+        let selected = get_prime_delegates(&state).await.expect("should get prime delegates");
+        assert_eq!(
+            selected,
+            Some(PrimeDelegates {
+                epoch: 5,
+                delegates: vec![],
+                max_power: 0,
+            })
+        );
+
+        // prime delegates are only selected once per epoch
+        state
+            .db
+            .lock()
+            .await
+            .set_prime_delegates(PrimeDelegates {
+                epoch: 6,
+                ..Default::default()
+            })
+            .await
+            .expect("should store prime delegates");
+        update_prime_delegates(&state, 6).await.expect("should keep prime delegates");
+        assert_eq!(get_prime_delegates(&state).await.expect("should get prime delegates").map(|p| p.epoch), Some(6));
+    }
+}
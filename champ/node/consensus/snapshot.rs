@@ -0,0 +1,248 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, Result};
+use pog_proto::api::{transaction::Data, AccountID};
+use prost::Message;
+
+use crate::consensus::fixed::Fixed;
+use crate::consensus::prime_delegates::{rank_accounts, EPOCH_LENGTH};
+use crate::consensus::voting_power::get_actual_power_before;
+use crate::state::ChampStateArc;
+
+#[derive(Clone, PartialEq, Message)]
+pub struct AccountPower {
+    #[prost(bytes = "vec", tag = "1")]
+    pub account: Vec<u8>,
+    /// voting power without delegated power
+    #[prost(uint32, tag = "2")]
+    pub actual: u32,
+    /// voting power with delegated power, limited to the max voting power
+    #[prost(uint32, tag = "3")]
+    pub active: u32,
+}
+
+/// Voting power of every account at the start of an epoch
+#[derive(Clone, PartialEq, Message)]
+pub struct PowerSnapshot {
+    #[prost(uint64, tag = "1")]
+    pub epoch: u64,
+    /// sorted by account
+    #[prost(message, repeated, tag = "2")]
+    pub accounts: Vec<AccountPower>,
+    /// max active voting power of a single account during the epoch
+    #[prost(uint32, tag = "3")]
+    pub max_power: u32,
+}
+
+/// Calculates the voting power of every account at the start of an epoch
+///
+/// Only blocks created before the epoch started count, so every node calculates the same snapshot no matter when
+/// it is taken. Active power is calculated like in `get_active_power`: an account's actual power plus the actual
+/// power of every account delegating to it, or nothing if the account delegates itself. Delegated power isn't
+/// reduced by the delegator's farming score, since the score is calculated from the latest blocks instead of the
+/// blocks before the epoch started. The max voting power is a share of the combined power of the accounts that
+/// become prime delegates, the active power of every account is limited to it.
+pub async fn take_snapshot(state: &ChampStateArc, epoch: u64) -> Result<PowerSnapshot> {
+    let params = state.config.read().await.consensus.consensus_params()?;
+    let cutoff = epoch.saturating_mul(EPOCH_LENGTH);
+    let mut accounts = state.db.lock().await.get_accounts().await?;
+    accounts.sort();
+
+    let mut actual_power = HashMap::with_capacity(accounts.len());
+    // accounts that created a block before the epoch started
+    let mut existing = HashSet::with_capacity(accounts.len());
+    for account in &accounts {
+        // accounts created after the epoch started don't have any voting power yet
+        let power = get_actual_power_before(state, *account, cutoff).await?;
+        if power.is_some() {
+            existing.insert(*account);
+        }
+        actual_power.insert(*account, power.unwrap_or(0) as u64);
+    }
+
+    let mut representatives = HashMap::new();
+    for account in &accounts {
+        match get_delegate_before(state, *account, cutoff).await? {
+            Some(representative) if &representative != account => {
+                representatives.insert(*account, representative);
+            }
+            _ => {}
+        }
+    }
+
+    // accounts that delegate vote through their representative, power delegated to them is not passed on.
+    // Representatives without blocks before the epoch started don't exist in the snapshot and don't get any power.
+    let mut active_power = actual_power.clone();
+    for (account, representative) in &representatives {
+        active_power.insert(*account, 0);
+        if existing.contains(representative) && !representatives.contains_key(representative) {
+            *active_power.entry(*representative).or_default() += actual_power[account];
+        }
+    }
+
+    let combined_power: u64 =
+        rank_accounts(active_power.iter().map(|(account, power)| (*account, *power))).iter().map(|(_, p)| p).sum();
+    let max_power = (Fixed::from_int(combined_power) * Fixed::from_f64(params.max_network_power)).to_u32();
+
+    Ok(PowerSnapshot {
+        epoch,
+        accounts: accounts
+            .into_iter()
+            .map(|account| AccountPower {
+                account: account.to_vec(),
+                actual: actual_power[&account].min(u32::MAX as u64) as u32,
+                active: active_power[&account].min(max_power as u64) as u32,
+            })
+            .collect(),
+        max_power,
+    })
+}
+
+/// Returns the representative an account delegated to before `timestamp`, `None` if it didn't delegate by then
+async fn get_delegate_before(state: &ChampStateArc, account: AccountID, timestamp: u64) -> Result<Option<AccountID>> {
+    let db = state.db.lock().await;
+    let block = match db.get_latest_block_by_account_before(account, timestamp, 0).await? {
+        Some(block) => block,
+        None => return Ok(None),
+    };
+    let height = block.data.as_ref().ok_or_else(|| anyhow!("block data not found"))?.height;
+
+    // the current representative is still the one from before the epoch if the account didn't create blocks since
+    let latest = db.get_latest_block_by_account(account).await?;
+    if latest.get_id().ok() == block.get_id().ok() {
+        return Ok(db.get_account_delegate(account).await?);
+    }
+
+    for height in (0..=height).rev() {
+        let block = match db.get_block_by_height(account, &height).await? {
+            Some(block) => block,
+            None => continue,
+        };
+        let representative =
+            block.data.into_iter().flat_map(|data| data.transactions).rev().find_map(|tx| match tx.data {
+                Some(Data::TxDelegate(tx)) => Some(tx.representative),
+                _ => None,
+            });
+        if let Some(representative) = representative {
+            let representative =
+                representative.try_into().map_err(|_| anyhow!("block delegates to an invalid account"))?;
+            return Ok(Some(representative));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::voting_power::get_actual_power;
+    use crate::state::ChampState;
+    use crypto::signatures::ed25519::{create_public_key, create_signature, generate_private_key};
+    use encoding::account::generate_account_address;
+    use pog_proto::api::signed_block::BlockData;
+    use pog_proto::api::transaction::TxDelegate;
+    use pog_proto::api::{SignedBlock, Transaction};
+
+    fn new_address() -> AccountID {
+        let private_key = generate_private_key().expect("should generate private key");
+        let public_key = create_public_key(&private_key).expect("should calculate public key");
+        generate_account_address(public_key.to_vec()).expect("should generate address")
+    }
+
+    /// Stores the blocks of a new account, given as timestamp, balance and the representative the block delegates to
+    async fn add_account(state: &ChampStateArc, blocks: &[(u64, u64, Option<AccountID>)]) -> AccountID {
+        let private_key = generate_private_key().expect("should generate private key");
+        let public_key = create_public_key(&private_key).expect("should calculate public key");
+        let mut previous = vec![];
+        for (height, (timestamp, balance, representative)) in blocks.iter().enumerate() {
+            let data = BlockData {
+                version: 0,
+                signature_type: 0,
+                balance: *balance,
+                height: height as u64,
+                previous: previous.clone(),
+                transactions: representative
+                    .iter()
+                    .map(|representative| Transaction {
+                        data: Some(Data::TxDelegate(TxDelegate {
+                            representative: representative.to_vec(),
+                        })),
+                    })
+                    .collect(),
+            };
+            let signature = create_signature(&data.encode_to_vec(), &private_key).expect("should sign block");
+            let block = SignedBlock {
+                signature: signature.to_vec(),
+                public_key: public_key.to_vec(),
+                timestamp: *timestamp,
+                data: Some(data),
+            };
+            previous = block.get_id().expect("should calculate block id").to_vec();
+            state.db.lock().await.add_block(block).await.expect("should add block");
+        }
+        generate_account_address(public_key.to_vec()).expect("should generate address")
+    }
+
+    fn power_of(snapshot: &PowerSnapshot, account: AccountID) -> Option<&AccountPower> {
+        snapshot.accounts.iter().find(|power| power.account == account)
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_cutoff() {
+        let state = ChampState::mock().await;
+        // the block at the start of the epoch is already too late
+        let account = add_account(&state, &[(0, 1000, None), (EPOCH_LENGTH, 10, None)]).await;
+        let first_block_only = add_account(&state, &[(0, 1000, None)]).await;
+        let created_later = add_account(&state, &[(EPOCH_LENGTH + 1, 1000, None)]).await;
+
+        let snapshot = take_snapshot(&state, 1).await.expect("should take snapshot");
+        let expected = get_actual_power(&state, first_block_only).await.expect("should calculate power");
+        assert_ne!(expected, get_actual_power(&state, account).await.expect("should calculate power"));
+        assert_eq!(power_of(&snapshot, account).map(|power| power.actual), Some(expected));
+        assert_eq!(power_of(&snapshot, created_later).map(|power| (power.actual, power.active)), Some((0, 0)));
+
+        // the snapshot of an epoch doesn't change once it started
+        add_account(&state, &[(EPOCH_LENGTH + 2, 5000, None)]).await;
+        let later = take_snapshot(&state, 1).await.expect("should take snapshot");
+        assert_eq!(later.max_power, snapshot.max_power);
+        assert_eq!(power_of(&later, account), power_of(&snapshot, account));
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_delegation() {
+        let state = ChampState::mock().await;
+        let cutoff = EPOCH_LENGTH;
+        let representative = add_account(&state, &[(0, 1000, None)]).await;
+        let delegator = add_account(&state, &[(0, 2000, Some(representative))]).await;
+        // delegates after the epoch started, so it still votes itself
+        let late_delegator = add_account(&state, &[(0, 3000, None), (cutoff + 10, 3000, Some(representative))]).await;
+        // delegates to an account that never created a block
+        let phantom = new_address();
+        let lost = add_account(&state, &[(0, 4000, Some(phantom))]).await;
+
+        let snapshot = take_snapshot(&state, 1).await.expect("should take snapshot");
+        let actual = |account| {
+            let state = state.clone();
+            async move {
+                get_actual_power_before(&state, account, cutoff)
+                    .await
+                    .expect("should calculate power")
+                    .expect("should have a block before the cutoff") as u64
+            }
+        };
+
+        // the phantom representative doesn't count towards the combined power
+        assert!(power_of(&snapshot, phantom).is_none());
+        let representative_power = actual(representative).await + actual(delegator).await;
+        let combined_power = representative_power + actual(late_delegator).await;
+        let params = state.config.read().await.consensus.consensus_params().expect("should have params");
+        let max_power = (Fixed::from_int(combined_power) * Fixed::from_f64(params.max_network_power)).to_u32();
+        assert_eq!(snapshot.max_power, max_power);
+
+        let active = |account| power_of(&snapshot, account).map(|power| power.active);
+        assert_eq!(active(representative), Some(representative_power.min(max_power as u64) as u32));
+        assert_eq!(active(delegator), Some(0));
+        assert_eq!(active(late_delegator), Some(actual(late_delegator).await.min(max_power as u64) as u32));
+        assert_eq!(active(lost), Some(0));
+    }
+}
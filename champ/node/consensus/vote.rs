@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+
+use crypto::signatures::ed25519::{create_signature, verify_signature};
+use encoding::account::generate_account_address;
+use pog_proto::api::{AccountID, BlockID};
+use prost::Message;
+use thiserror::Error;
+
+use crate::chain::ChainError;
+use crate::consensus::prime_delegates::get_prime_delegates;
+use crate::state::ChampStateArc;
+
+/// A prime delegate's vote for a block
+#[derive(Clone, PartialEq, Message)]
+pub struct Vote {
+    #[prost(bytes = "vec", tag = "1")]
+    pub block_id: Vec<u8>,
+    /// height of the block in its account's chain
+    #[prost(uint64, tag = "2")]
+    pub height: u64,
+    #[prost(bytes = "vec", tag = "3")]
+    pub voter: Vec<u8>,
+    /// voting power of the voter in the snapshot of the vote's epoch
+    #[prost(uint32, tag = "4")]
+    pub power: u32,
+    #[prost(bytes = "vec", tag = "5")]
+    pub public_key: Vec<u8>,
+    #[prost(bytes = "vec", tag = "6")]
+    pub signature: Vec<u8>,
+    /// epoch of the voting power snapshot the power is taken from
+    #[prost(uint64, tag = "7")]
+    pub epoch: u64,
+    /// identity of the chain and consensus params the vote was cast with
+    #[prost(bytes = "vec", tag = "8")]
+    pub chain: Vec<u8>,
+}
+
+#[derive(Error, Debug)]
+pub enum VoteError {
+    #[error("invalid block id")]
+    InvalidBlockId,
+    #[error("voter does not match the public key")]
+    InvalidVoter,
+    #[error("invalid signature")]
+    InvalidSignature,
+    #[error("voter is not a prime delegate")]
+    NotPrimeDelegate,
+    #[error("voting power does not match the voter's power in this epoch")]
+    PowerMismatch,
+    #[error("vote is not for the current epoch")]
+    WrongEpoch,
+    #[error("vote is for a different chain or different consensus params")]
+    WrongChain,
+    #[error("chain configuration is invalid: {0}")]
+    ChainError(#[from] ChainError),
+    #[error("error signing vote")]
+    SigningError,
+    #[error("prime delegates could not be loaded: {0}")]
+    PrimeDelegatesError(anyhow::Error),
+}
+
+impl Vote {
+    /// Returns the bytes the voter signs, which is the encoded vote without its signature
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        Vote {
+            signature: vec![],
+            ..self.clone()
+        }
+        .encode_to_vec()
+    }
+
+    pub fn sign(&mut self, private_key: &[u8]) -> Result<(), VoteError> {
+        let signature = create_signature(&self.signing_bytes(), private_key).map_err(|_| VoteError::SigningError)?;
+        self.signature = signature.to_vec();
+        Ok(())
+    }
+
+    pub fn get_block_id(&self) -> Result<BlockID, VoteError> {
+        self.block_id.clone().try_into().map_err(|_| VoteError::InvalidBlockId)
+    }
+
+    pub fn get_voter(&self) -> Result<AccountID, VoteError> {
+        let voter: AccountID = self.voter.clone().try_into().map_err(|_| VoteError::InvalidVoter)?;
+        match generate_account_address(self.public_key.clone()) {
+            Ok(address) if address == voter => Ok(voter),
+            _ => Err(VoteError::InvalidVoter),
+        }
+    }
+}
+
+/// Verifies that a vote was signed by its voter and that the voter is a prime delegate with the claimed power
+///
+/// Votes have to reference this node's chain identity and the current epoch, the voter's power is its power in the epoch's voting power snapshot,
+/// so every node weighs the vote the same.
+pub async fn verify_vote(vote: &Vote, state: &ChampStateArc) -> Result<(), VoteError> {
+    vote.get_block_id()?;
+    let voter = vote.get_voter()?;
+    verify_signature(&vote.signing_bytes(), &vote.public_key, &vote.signature)
+        .map_err(|_| VoteError::InvalidSignature)?;
+
+    if vote.chain != state.config.read().await.consensus.chain_identity()? {
+        return Err(VoteError::WrongChain);
+    }
+
+    let prime_delegates = get_prime_delegates(state).await.map_err(VoteError::PrimeDelegatesError)?;
+    let prime_delegates = prime_delegates.ok_or(VoteError::NotPrimeDelegate)?;
+    if prime_delegates.epoch != vote.epoch {
+        return Err(VoteError::WrongEpoch);
+    }
+    let power = prime_delegates.get_power(&voter).ok_or(VoteError::NotPrimeDelegate)?;
+    if power != vote.power {
+        return Err(VoteError::PowerMismatch);
+    }
+    Ok(())
+}
+
+/// Outcome of the votes for a block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoteResult {
+    /// The block received votes of more than two thirds of the active voting power
+    Resolved {
+        power: u64,
+    },
+    Unresolved {
+        power: u64,
+        quorum: u64,
+    },
+}
+
+impl VoteResult {
+    pub fn new(power: u64, total_power: u64) -> Self {
+        let quorum = quorum(total_power);
+        if power >= quorum {
+            VoteResult::Resolved {
+                power,
+            }
+        } else {
+            VoteResult::Unresolved {
+                power,
+                quorum,
+            }
+        }
+    }
+}
+
+/// Votes collected for a single block
+#[derive(Debug, Default)]
+pub struct VoteTally {
+    // only the latest vote of each voter counts
+    votes: HashMap<AccountID, u32>,
+}
+
+impl VoteTally {
+    pub fn add(&mut self, voter: AccountID, power: u32) {
+        self.votes.insert(voter, power);
+    }
+
+    pub fn power(&self) -> u64 {
+        self.votes.values().map(|power| *power as u64).sum()
+    }
+
+    pub fn result(&self, total_power: u64) -> VoteResult {
+        VoteResult::new(self.power(), total_power)
+    }
+}
+
+/// Returns the voting power needed to resolve a vote, more than two thirds of the total power
+pub fn quorum(total_power: u64) -> u64 {
+    total_power * 2 / 3 + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto::signatures::ed25519::{create_public_key, generate_private_key};
+
+    #[test]
+    fn test_vote_signature() {
+        let private_key = generate_private_key().expect("should generate private key");
+        let public_key = create_public_key(&private_key).expect("should calculate public key");
+        let mut vote = Vote {
+            block_id: vec![1; 32],
+            height: 3,
+            voter: generate_account_address(public_key.to_vec()).expect("should generate address").to_vec(),
+            power: 10,
+            public_key: public_key.to_vec(),
+            signature: vec![],
+            epoch: 1,
+            chain: vec![],
+        };
+        vote.sign(&private_key).expect("should sign vote");
+        assert!(vote.get_voter().is_ok());
+        assert!(verify_signature(&vote.signing_bytes(), &vote.public_key, &vote.signature).is_ok());
+
+        vote.power = 20;
+        assert!(verify_signature(&vote.signing_bytes(), &vote.public_key, &vote.signature).is_err());
+
+        vote.voter = vec![0; 24];
+        assert!(matches!(vote.get_voter(), Err(VoteError::InvalidVoter)));
+    }
+
+    #[test]
+    fn test_vote_tally() {
+        let mut tally = VoteTally::default();
+        tally.add([1; 24], 30);
+        tally.add([2; 24], 30);
+        // voting twice doesn't count twice
+        tally.add([2; 24], 30);
+        assert_eq!(
+            tally.result(100),
+            VoteResult::Unresolved {
+                power: 60,
+                quorum: 67
+            }
+        );
+
+        tally.add([3; 24], 7);
+        assert_eq!(
+            tally.result(100),
+            VoteResult::Resolved {
+                power: 67
+            }
+        );
+    }
+}
@@ -1,137 +1,551 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
 use anyhow::{anyhow, Result};
+use tokio::sync::Semaphore;
+use tokio::task::JoinHandle;
 use tracing::{debug, trace};
 
+use crate::chain::ConsensusParams;
+use crate::consensus::farming::dampen_delegated_power;
+use crate::consensus::fixed::Fixed;
 use crate::consensus::graphs::*;
+use crate::consensus::prime_delegates::{current_epoch, get_prime_delegates};
 use crate::state::ChampStateArc;
 use pog_proto::api;
 
-// To balance each graph
-const BLOCK_WEIGHT: f64 = 1.2;
-const BALANCE_WEIGHT: f64 = 0.75;
-const CASHFLOW_WEIGHT: f64 = 1.0;
-const AGE_WEIGHT: f64 = 1.0;
-const INACTIVE_TAX_WEIGHT: f64 = 1.0;
+/// Max number of delegators whose power is calculated at the same time
+const MAX_CONCURRENT_DELEGATORS: usize = 16;
+
+/// The graph results an account's actual voting power is made of, before any weights are applied
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ActualPowerBreakdown {
+    pub balance: Fixed,
+    pub cashflow: Fixed,
+    pub block: Fixed,
+    pub age: Fixed,
+    pub inactive_tax: Fixed,
+    /// the weighted sum of all graphs
+    pub power: u32,
+}
 
-// so we can normalize the network affect
-const MAX_NETWORK_POWER: f64 = 0.3;
+/// How much impact each graph has on the actual voting power, taken from the consensus params
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PowerWeights {
+    pub balance: Fixed,
+    pub cashflow: Fixed,
+    pub block: Fixed,
+    pub age: Fixed,
+    pub inactive_tax: Fixed,
+}
 
-// Month in Seconds
-const LOOKBACK_RANGE: u64 = 60 * 60 * 24 * 30;
-// 2 Months in Seconds
-const MAX_LOOKBACK_RANGE: u64 = 60 * 60 * 24 * 30 * 2;
+impl PowerWeights {
+    pub fn new(params: &ConsensusParams) -> Self {
+        Self {
+            balance: Fixed::from_f64(params.balance_weight),
+            cashflow: Fixed::from_f64(params.cashflow_weight),
+            block: Fixed::from_f64(params.block_weight),
+            age: Fixed::from_f64(params.age_weight),
+            inactive_tax: Fixed::from_f64(params.inactive_tax_weight),
+        }
+    }
+}
+
+/// Everything the active voting power of an account is calculated from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActivePowerBreakdown {
+    pub actual: ActualPowerBreakdown,
+    /// the combined power of all delegators
+    pub delegated: u32,
+    /// max voting power of a single account, `None` until the first prime delegates are selected
+    pub max_power: Option<u32>,
+    /// actual and delegated power, limited to the max voting power
+    pub power: u32,
+}
+
+/// Actual power of every account calculated during the current epoch, together with the latest block it was
+/// calculated at
+///
+/// The actual power only depends on an account's own blocks, so it stays valid until the account creates a new block
+/// or its latest block is rolled back. Consensus params can't change while the node is running.
+/// The cache is emptied whenever a new epoch starts, so it only grows with the accounts whose power was needed since.
+#[derive(Debug, Default)]
+pub struct PowerCache {
+    cached: Mutex<CachedPower>,
+}
+
+#[derive(Debug, Default)]
+struct CachedPower {
+    epoch: u64,
+    accounts: HashMap<api::AccountID, (api::BlockID, ActualPowerBreakdown)>,
+}
+
+impl PowerCache {
+    fn get(&self, account_id: &api::AccountID, block_id: &api::BlockID) -> Option<ActualPowerBreakdown> {
+        match self.accounts(current_epoch()).accounts.get(account_id) {
+            Some((cached_block_id, breakdown)) if cached_block_id == block_id => Some(*breakdown),
+            _ => None,
+        }
+    }
+
+    fn insert(&self, account_id: api::AccountID, block_id: api::BlockID, breakdown: ActualPowerBreakdown) {
+        self.accounts(current_epoch()).accounts.insert(account_id, (block_id, breakdown));
+    }
+
+    /// Locks the power cached during an epoch, power cached during an earlier epoch is dropped
+    fn accounts(&self, epoch: u64) -> MutexGuard<'_, CachedPower> {
+        let mut cached = self.cached.lock().expect("power cache lock should not be poisoned");
+        if cached.epoch != epoch {
+            cached.epoch = epoch;
+            cached.accounts.clear();
+        }
+        cached
+    }
+}
 
 /// Returns actual voting power of an account.
 /// Actual voting power is without the delegated power.
 #[tracing::instrument]
 pub async fn get_actual_power(state: &ChampStateArc, account_id: api::AccountID) -> Result<u32> {
+    Ok(get_actual_power_breakdown(state, account_id).await?.power)
+}
+
+/// Calculates the actual voting power of an account together with the result of every graph
+#[tracing::instrument]
+pub async fn get_actual_power_breakdown(
+    state: &ChampStateArc,
+    account_id: api::AccountID,
+) -> Result<ActualPowerBreakdown> {
     debug!("Calculating actual voting power");
 
-    let db = &state.db.lock().await;
+    let block = state.db.lock().await.get_latest_block_by_account(account_id).await?;
+    let block_id = block.get_id().map_err(|_| anyhow!("block id could not be created"))?;
+    if let Some(breakdown) = state.power_cache.get(&account_id, &block_id) {
+        trace!("cached actual voting power result: {}", breakdown.power);
+        return Ok(breakdown);
+    }
 
-    let block = db.get_latest_block_by_account(account_id).await?;
-    let data = block.data.as_ref().ok_or_else(|| anyhow!("block data not found"))?;
+    let breakdown = calculate_actual_power(state, account_id, &block).await?;
+    state.power_cache.insert(account_id, block_id, breakdown);
+    Ok(breakdown)
+}
+
+/// Returns the actual voting power an account had at a point in time, calculated from its latest block before it
+///
+/// Returns `None` if the account didn't have any blocks yet.
+pub async fn get_actual_power_before(
+    state: &ChampStateArc,
+    account_id: api::AccountID,
+    timestamp: u64,
+) -> Result<Option<u32>> {
+    let block = state.db.lock().await.get_latest_block_by_account_before(account_id, timestamp, 0).await?;
+    match block {
+        Some(block) => Ok(Some(calculate_actual_power(state, account_id, &block).await?.power)),
+        None => Ok(None),
+    }
+}
 
-    // Block from between lookback range and max lookback range
-    let old_block_result = db
-        .get_latest_block_by_account_before(
-            account_id,
-            block.timestamp - LOOKBACK_RANGE,
-            block.timestamp - MAX_LOOKBACK_RANGE,
-        )
-        .await?;
+/// Calculates the actual voting power of an account as of one of its blocks
+async fn calculate_actual_power(
+    state: &ChampStateArc,
+    account_id: api::AccountID,
+    block: &api::SignedBlock,
+) -> Result<ActualPowerBreakdown> {
+    let params = state.config.read().await.consensus.consensus_params()?;
+    let data = block.data.as_ref().ok_or_else(|| anyhow!("block data not found"))?;
+    let db = state.db.lock().await;
 
     // First Block from an account
     let first_block = db.get_block_by_height(account_id, &0).await?.ok_or_else(|| anyhow!("no block found"))?;
 
+    // The latest block created more than the lookback range before the block, its balance is the balance at
+    // the start of the lookback range even if the account was inactive for longer.
+    // Accounts younger than the lookback range are compared against their first block.
+    let old_block = db
+        .get_latest_block_by_account_before(account_id, block.timestamp.saturating_sub(params.lookback_range), 0)
+        .await?
+        .unwrap_or_else(|| first_block.clone());
+    drop(db);
+
     let new_block_balance = data.balance;
-    let old_block_balance = old_block_result
-        .clone()
-        .ok_or_else(|| anyhow!("block not found"))?
-        .data
-        .ok_or_else(|| anyhow!("block data not found"))?
-        .balance;
-
-    let bresult = balance_graph(data.balance);
-    let cresult = cashflow_graph(new_block_balance, old_block_balance);
-    let bbresult = block_graph(data.height, &block, old_block_result.as_ref());
-    let aresult = age_graph(block.timestamp - first_block.timestamp);
+    let old_block_balance = old_block.data.as_ref().ok_or_else(|| anyhow!("block data not found"))?.balance;
+
+    let bresult = balance_graph(&params, data.balance);
+    let cresult = cashflow_graph(&params, new_block_balance, old_block_balance);
+    let bbresult = block_graph(&params, data.height, block, Some(&old_block));
+    let aresult = age_graph(&params, block.timestamp.saturating_sub(first_block.timestamp));
 
     // Weights to change how much impact each factor should have
+    let weights = PowerWeights::new(&params);
     let net_result =
-        bbresult * BLOCK_WEIGHT + bresult * BALANCE_WEIGHT + aresult * AGE_WEIGHT + cresult * CASHFLOW_WEIGHT;
+        bbresult * weights.block + bresult * weights.balance + aresult * weights.age + cresult * weights.cashflow;
 
-    let iresult = inactive_tax_graph(new_block_balance, old_block_balance, net_result);
+    let iresult = inactive_tax_graph(&params, new_block_balance, old_block_balance, net_result);
 
     trace!("Graph results: balance={0}, cashflow={1}, block={2}, age={3}", bresult, cresult, bbresult, aresult);
     // TODO: Green Adresses?
 
-    let graph_result = net_result + iresult * INACTIVE_TAX_WEIGHT;
+    let graph_result = net_result + iresult * weights.inactive_tax;
 
-    let result = if graph_result < 0.0 {
-        0
-    } else {
-        graph_result as u32
-    };
+    // negative results are no voting power, the fractional part is truncated
+    let result = graph_result.to_u32();
 
     trace!("total actual voting power result: {}", result);
 
-    Ok(result)
+    Ok(ActualPowerBreakdown {
+        balance: bresult,
+        cashflow: cresult,
+        block: bbresult,
+        age: aresult,
+        inactive_tax: iresult,
+        power: result,
+    })
 }
 
 /// Returns the active power of an account that is being used on the network.
-/// Active power is the account power with the delegated power.
+/// Active power is the account power with the delegated power, accounts that delegate their power have none.
 #[tracing::instrument]
 pub async fn get_active_power(state: &ChampStateArc, account_id: api::AccountID) -> Result<u32> {
-    debug!("Calculating actual voting power");
-    let actual_power = get_actual_power(state, account_id).await?;
-    let delegate_power = get_delegated_power(state, account_id).await?;
-    let total_network_power = get_max_voting_power();
-    let total_power = actual_power + delegate_power;
-    if total_power > total_network_power {
-        return Ok(total_network_power);
+    Ok(get_active_power_breakdown(state, account_id).await?.power)
+}
+
+/// Calculates the active voting power of an account together with everything it is made of
+#[tracing::instrument]
+pub async fn get_active_power_breakdown(
+    state: &ChampStateArc,
+    account_id: api::AccountID,
+) -> Result<ActivePowerBreakdown> {
+    debug!("Calculating active voting power");
+    let actual = get_actual_power_breakdown(state, account_id).await?;
+    let max_power = get_max_voting_power(state).await?;
+
+    // the account's power counts for its representative, power delegated to the account is not passed on
+    let representative = state.db.lock().await.get_account_delegate(account_id).await?;
+    if representative.map_or(false, |representative| representative != account_id) {
+        trace!("account delegates its voting power");
+        return Ok(ActivePowerBreakdown {
+            actual,
+            delegated: 0,
+            max_power,
+            power: 0,
+        });
     }
-    trace!("total active voting power result: {}", total_power);
-    Ok(total_power)
+
+    let delegators = get_delegated_power(state, account_id).await?;
+    let delegated = delegators.iter().fold(0_u32, |sum, (_, power)| sum.saturating_add(*power));
+
+    let total_power = actual.power.saturating_add(delegated);
+    let power = max_power.map_or(total_power, |max_power| total_power.min(max_power));
+    trace!("total active voting power result: {}", power);
+
+    Ok(ActivePowerBreakdown {
+        actual,
+        delegated,
+        max_power,
+        power,
+    })
 }
 
-/// Gets the sum of the power of each delegate of an account
-async fn get_delegated_power(state: &ChampStateArc, account_id: api::AccountID) -> Result<u32> {
+/// Gets the power each delegate of an account contributes to it
+///
+/// Delegation is single-hop: delegates contribute their actual power, power delegated to them is not passed on.
+/// The delegates' power is calculated concurrently, at most `MAX_CONCURRENT_DELEGATORS` at a time.
+async fn get_delegated_power(state: &ChampStateArc, account_id: api::AccountID) -> Result<Vec<(api::AccountID, u32)>> {
     debug!("calculating delegated power");
-    // TODO: Cache this
-    let mut power = 0;
-    let db = &state.db.lock().await;
-
-    let mut delegates = db.get_delegates_by_account(account_id).await?;
-    // TODO: Test Performance and do this concurrently?
-    while let Some(d) = delegates.pop() {
-        let p = get_actual_power(state, d.to_owned()).await?;
-        power += p;
+    // the lock is released right away, calculating the power of the delegates locks the database again
+    let mut delegates = state.db.lock().await.get_delegates_by_account(account_id).await?;
+    // rejected by validation, but would count the account's power twice
+    delegates.retain(|delegate| delegate != &account_id);
+
+    let permits = Arc::new(Semaphore::new(MAX_CONCURRENT_DELEGATORS));
+    let mut tasks: Vec<(api::AccountID, JoinHandle<Result<u32>>)> = Vec::with_capacity(delegates.len());
+    for delegate in delegates {
+        let s = state.clone();
+        let permits = permits.clone();
+        let task = tokio::spawn(async move {
+            let _permit = permits.acquire_owned().await?;
+            let power = get_actual_power(&s, delegate).await?;
+            dampen_delegated_power(&s, delegate, power).await
+        });
+        tasks.push((delegate, task));
     }
 
-    trace!("total delegated voting power: {}", power);
-    Ok(power)
+    let mut delegators = Vec::with_capacity(tasks.len());
+    for (delegate, task) in tasks {
+        delegators.push((delegate, task.await??));
+    }
+
+    trace!("delegated voting power: {:?}", delegators);
+    Ok(delegators)
+}
+
+/// Gets the combined voting power of all prime delegates of the current epoch
+pub async fn get_prime_delegate_power(state: &ChampStateArc) -> Result<u64> {
+    let prime_delegates = get_prime_delegates(state).await?;
+    Ok(prime_delegates.map(|prime_delegates| prime_delegates.total_power()).unwrap_or(0))
 }
 
-/// Gets the max voting power in the system and sets a limit of a percentage
-fn get_max_voting_power() -> u32 {
-    //TODO: Get all voting power of all prime delegates combined
-    let total_prime_delegate_power = 100_000_000_f64;
-    (total_prime_delegate_power * MAX_NETWORK_POWER) as u32
+/// Gets the max voting power in the system, a percentage of the prime delegates' combined power
+///
+/// There is no limit until the first prime delegates are selected.
+async fn get_max_voting_power(state: &ChampStateArc) -> Result<Option<u32>> {
+    let prime_delegates = get_prime_delegates(state).await?;
+    Ok(prime_delegates.map(|prime_delegates| prime_delegates.max_power))
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::consensus::{
-        graphs::{balance_graph, cashflow_graph},
-        voting_power::BALANCE_WEIGHT,
-        voting_power::CASHFLOW_WEIGHT,
-    };
+    use super::*;
+    use crate::consensus::graphs::{balance_graph, cashflow_graph};
+    use crate::state::ChampState;
+    use crypto::signatures::ed25519::{create_public_key, create_signature, generate_private_key};
+    use encoding::account::generate_account_address;
     use pog_proto::api::signed_block::BlockData;
-    use pog_proto::api::SignedBlock;
+    use pog_proto::api::transaction::{Data, TxDelegate};
+    use pog_proto::api::{SignedBlock, Transaction};
+    use prost::Message;
+    use quickcheck::{Arbitrary, Gen, QuickCheck};
+
+    const DAY: u64 = 60 * 60 * 24;
+
+    /// Random account history as timestamp and balance, blocks are up to 20 days apart
+    #[derive(Debug, Clone)]
+    struct History(Vec<(u64, u64)>);
+
+    impl Arbitrary for History {
+        fn arbitrary(g: &mut Gen) -> Self {
+            let len = 1 + usize::arbitrary(g) % 6;
+            let mut timestamp = 0;
+            let blocks = (0..len)
+                .map(|_| {
+                    timestamp += u64::arbitrary(g) % (20 * DAY);
+                    (timestamp, u64::arbitrary(g) % 1_000_000)
+                })
+                .collect();
+            History(blocks)
+        }
+    }
+
+    /// Runs an async property on a new runtime and state, quickcheck only supports sync functions
+    fn check_history<F, Fut>(history: &History, property: F) -> bool
+    where
+        F: FnOnce(ChampStateArc, api::AccountID) -> Fut,
+        Fut: std::future::Future<Output = bool>,
+    {
+        let runtime =
+            tokio::runtime::Builder::new_current_thread().enable_all().build().expect("should create runtime");
+        runtime.block_on(async {
+            let state = ChampState::mock().await;
+            let account = add_account(&state, &history.0).await;
+            property(state, account).await
+        })
+    }
+
+    /// Stores the blocks of a new account, given as timestamp and balance
+    async fn add_account(state: &ChampStateArc, blocks: &[(u64, u64)]) -> api::AccountID {
+        add_delegating_account(state, blocks, None).await
+    }
+
+    /// Stores the blocks of a new account whose first block delegates to the representative
+    async fn add_delegating_account(
+        state: &ChampStateArc,
+        blocks: &[(u64, u64)],
+        representative: Option<api::AccountID>,
+    ) -> api::AccountID {
+        let private_key = generate_private_key().expect("should generate private key");
+        let public_key = create_public_key(&private_key).expect("should calculate public key");
+        let mut previous = vec![];
+        for (height, (timestamp, balance)) in blocks.iter().enumerate() {
+            let data = BlockData {
+                version: 0,
+                signature_type: 0,
+                balance: *balance,
+                height: height as u64,
+                previous: previous.clone(),
+                transactions: match representative {
+                    Some(representative) if height == 0 => vec![Transaction {
+                        data: Some(Data::TxDelegate(TxDelegate {
+                            representative: representative.to_vec(),
+                        })),
+                    }],
+                    _ => vec![],
+                },
+            };
+            let signature = create_signature(&data.encode_to_vec(), &private_key).expect("should sign block");
+            let block = SignedBlock {
+                signature: signature.to_vec(),
+                public_key: public_key.to_vec(),
+                timestamp: *timestamp,
+                data: Some(data),
+            };
+            previous = block.get_id().expect("should calculate block id").to_vec();
+            state.db.lock().await.add_block(block).await.expect("should add block");
+        }
+        generate_account_address(public_key.to_vec()).expect("should generate address")
+    }
+
+    #[tokio::test]
+    async fn test_power_breakdown() {
+        let state = ChampState::mock().await;
+        let account = add_account(&state, &[(0, 1000), (40 * DAY, 900)]).await;
+
+        let breakdown = get_active_power_breakdown(&state, account).await.expect("should calculate power");
+        let actual = breakdown.actual;
+        assert_eq!(actual.cashflow, Fixed::from_int(100));
+        let params = state.config.read().await.consensus.consensus_params().expect("should have params");
+        let weights = PowerWeights::new(&params);
+        let weighted = actual.balance * weights.balance
+            + actual.cashflow * weights.cashflow
+            + actual.block * weights.block
+            + actual.age * weights.age
+            + actual.inactive_tax * weights.inactive_tax;
+        assert_eq!(actual.power, weighted.to_u32());
+        assert_eq!(actual.power, get_actual_power(&state, account).await.expect("should calculate power"));
+
+        // no limit before the first prime delegates are selected
+        assert_eq!(breakdown.max_power, None);
+        assert_eq!(breakdown.power, actual.power + breakdown.delegated);
+        assert_eq!(breakdown.power, get_active_power(&state, account).await.expect("should calculate power"));
+    }
+
+    #[tokio::test]
+    async fn test_actual_power_before() {
+        let state = ChampState::mock().await;
+        let account = add_account(&state, &[(0, 1000), (40 * DAY, 900)]).await;
+        let first_block_only = add_account(&state, &[(0, 1000)]).await;
+
+        let power = |timestamp| get_actual_power_before(&state, account, timestamp);
+        assert_eq!(power(0).await.expect("should calculate power"), None);
+        let expected = get_actual_power(&state, first_block_only).await.expect("should calculate power");
+        assert_eq!(power(40 * DAY).await.expect("should calculate power"), Some(expected));
+        let expected = get_actual_power(&state, account).await.expect("should calculate power");
+        assert_eq!(power(40 * DAY + 1).await.expect("should calculate power"), Some(expected));
+    }
+
+    #[tokio::test]
+    async fn test_delegated_power() {
+        let state = ChampState::mock().await;
+        let representative = add_account(&state, &[(0, 1000)]).await;
+        let delegator = add_delegating_account(&state, &[(0, 1000)], Some(representative)).await;
+        // delegates to the delegator, its power isn't passed on to the representative
+        let indirect = add_delegating_account(&state, &[(0, 5000)], Some(delegator)).await;
+
+        let actual = |account| {
+            let state = state.clone();
+            async move { get_actual_power(&state, account).await.expect("should calculate power") }
+        };
+        assert!(actual(delegator).await > 0);
+
+        let breakdown = get_active_power_breakdown(&state, representative).await.expect("should calculate power");
+        let delegators = get_delegated_power(&state, representative).await.expect("should calculate power");
+        assert_eq!(delegators, vec![(delegator, actual(delegator).await)]);
+        assert_eq!(breakdown.delegated, actual(delegator).await);
+        assert_eq!(breakdown.power, actual(representative).await + actual(delegator).await);
+
+        let delegates = state.db.lock().await.get_delegates_by_account(representative).await;
+        assert_eq!(delegates.expect("should get delegates"), vec![delegator]);
+
+        // accounts that delegate vote through their representative, so they don't have any active power
+        assert_eq!(get_active_power(&state, delegator).await.expect("should calculate power"), 0);
+        assert_eq!(get_active_power(&state, indirect).await.expect("should calculate power"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_power_cache() {
+        let state = ChampState::mock().await;
+        let account = add_account(&state, &[(0, 1000), (40 * DAY, 900)]).await;
+        let latest = state.db.lock().await.get_latest_block_by_account(account).await.expect("should have a block");
+        let latest_id = latest.get_id().expect("should calculate block id");
+        assert_eq!(state.power_cache.get(&account, &latest_id), None);
+
+        let actual = get_actual_power_breakdown(&state, account).await.expect("should calculate power");
+        assert_eq!(state.power_cache.get(&account, &latest_id), Some(actual));
+        // only valid for the block it was calculated at
+        assert_eq!(state.power_cache.get(&account, &[0; 32]), None);
+        assert_eq!(get_actual_power_breakdown(&state, account).await.expect("should calculate power"), actual);
+
+        // dropped once the next epoch starts
+        assert!(state.power_cache.accounts(current_epoch() + 1).accounts.is_empty());
+        assert_eq!(state.power_cache.get(&account, &latest_id), None);
+    }
+
+    #[tokio::test]
+    async fn test_young_account() {
+        let state = ChampState::mock().await;
+
+        // a single block at a timestamp smaller than the lookback range
+        let account = add_account(&state, &[(DAY, 1000)]).await;
+        let actual = get_actual_power_breakdown(&state, account).await.expect("should calculate power");
+        assert_eq!(actual.cashflow, Fixed::ZERO);
+        assert_eq!(actual.block, Fixed::from_f64(ConsensusParams::default().normalize_block));
+
+        // compared against the first block while younger than the lookback range
+        let account = add_account(&state, &[(0, 1000), (DAY, 900)]).await;
+        let actual = get_actual_power_breakdown(&state, account).await.expect("should calculate power");
+        assert_eq!(actual.cashflow, Fixed::from_int(100));
+    }
+
+    #[tokio::test]
+    async fn test_inactive_account() {
+        let state = ChampState::mock().await;
+
+        // inactive for longer than the lookback range, compared against the balance at its start
+        let account = add_account(&state, &[(0, 1000), (DAY, 800), (100 * DAY, 500)]).await;
+        let actual = get_actual_power_breakdown(&state, account).await.expect("should calculate power");
+        assert_eq!(actual.cashflow, Fixed::from_int(300));
+    }
+
+    #[test]
+    fn prop_cashflow_over_lookback_range() {
+        fn property(history: History) -> bool {
+            let lookback_range = ConsensusParams::default().lookback_range;
+            let (latest_timestamp, latest_balance) = *history.0.last().expect("history should have blocks");
+            // the latest block older than the lookback range, otherwise the first block
+            let (_, old_balance) = history
+                .0
+                .iter()
+                .rev()
+                .find(|(timestamp, _)| *timestamp < latest_timestamp.saturating_sub(lookback_range))
+                .unwrap_or(&history.0[0]);
+            let expected = Fixed::from_int(*old_balance as i128 - latest_balance as i128);
+
+            check_history(&history, |state, account| async move {
+                match get_actual_power_breakdown(&state, account).await {
+                    Ok(actual) => actual.cashflow == expected,
+                    Err(_) => false,
+                }
+            })
+        }
+        QuickCheck::new().tests(30).quickcheck(property as fn(History) -> bool);
+    }
+
+    #[test]
+    fn prop_power_is_deterministic() {
+        fn property(history: History) -> bool {
+            let blocks = history.0.clone();
+            check_history(&history, |state, account| async move {
+                let first = get_active_power(&state, account).await.ok();
+                // a second node that stored the same history, without the power cached by the first one
+                let other = ChampState::mock().await;
+                let other_account = add_account(&other, &blocks).await;
+                let second = get_active_power(&other, other_account).await.ok();
+
+                let latest = state.db.lock().await.get_latest_block_by_account(account).await;
+                let uncached = match latest {
+                    Ok(block) => calculate_actual_power(&state, account, &block).await.ok().map(|actual| actual.power),
+                    Err(_) => None,
+                };
+                first.is_some() && first == second && first == uncached
+            })
+        }
+        QuickCheck::new().tests(30).quickcheck(property as fn(History) -> bool);
+    }
+
     #[test]
     fn check_voting_power() {
         // Switch on to output debug table
         const TEST_TABLE_ON: bool = false;
+        let params = ConsensusParams::default();
 
         let blocks = vec![
             SignedBlock {
@@ -286,8 +700,9 @@ mod tests {
                     transactions: vec![],
                 },
             };
-            let balance_importance = balance_graph(new_data.balance) * BALANCE_WEIGHT;
-            let cashflow_importance = cashflow_graph(new_data.balance, old_data.balance) * CASHFLOW_WEIGHT;
+            let balance_importance = balance_graph(&params, new_data.balance) * Fixed::from_f64(params.balance_weight);
+            let cashflow_importance =
+                cashflow_graph(&params, new_data.balance, old_data.balance) * Fixed::from_f64(params.cashflow_weight);
             let total_importance = balance_importance + cashflow_importance;
             println!(
                 "{0} \t|----| {1} \t|----| {2} \t|----| {3} \t|----| {4}",
@@ -7,6 +7,8 @@ use hyper::service::{make_service_fn, service_fn};
 use hyper::{Body, Request, Response, Server};
 use tracing::info;
 
+use crate::shutdown::Shutdown;
+
 #[derive(Debug)]
 pub struct HttpServer {}
 
@@ -15,7 +17,12 @@ impl HttpServer {
         HttpServer {}
     }
 
-    pub async fn start(&self, addr: SocketAddr, enable: bool) -> Result<(), Box<dyn std::error::Error>> {
+    pub async fn start(
+        &self,
+        addr: SocketAddr,
+        enable: bool,
+        shutdown: Shutdown,
+    ) -> Result<(), Box<dyn std::error::Error>> {
         if !enable {
             return Ok(());
         }
@@ -26,7 +33,7 @@ impl HttpServer {
 
         let service = ServiceBuilder::new().timeout(Duration::from_secs(30)).service(http_service);
 
-        Server::bind(&addr).serve(service).await?;
+        Server::bind(&addr).serve(service).with_graceful_shutdown(shutdown.recv()).await?;
         Ok(())
     }
 }
@@ -1,23 +1,29 @@
 mod auth;
 mod blockpool;
+mod chain;
 mod cli;
 mod config;
 mod consensus;
 mod http;
 mod metrics;
+mod mode;
 mod p2p;
 mod rpc;
+mod shutdown;
 mod state;
 pub mod storage;
 pub mod validation;
 pub mod wallets;
 
+use std::time::Duration;
+
 use anyhow::Result;
 use http::HttpServer;
 use roughtime::server::RoughTime;
 use tokio::{sync::RwLock, try_join};
-use tracing::{debug, trace, Level};
+use tracing::{debug, error, info, trace, warn, Level};
 
+pub use crate::shutdown::ExitStatus;
 use crate::{
     blockpool::Blockpool,
     metrics::MetricsServer,
@@ -27,7 +33,10 @@ use crate::{
     wallets::WalletManager,
 };
 
-pub async fn run() -> Result<()> {
+/// How long each shutdown step can take before the node exits anyway
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub async fn run() -> Result<ExitStatus> {
     let matches = cli::parser::new();
     let log_level = match matches.value_of("loglevel") {
         Some("trace") => Level::TRACE,
@@ -46,6 +55,15 @@ pub async fn run() -> Result<()> {
 
     debug!("loading config");
     let config = config::Config::new(Some(matches.clone()))?;
+    // fail early instead of rejecting every block later on
+    config.consensus.protocol_params()?;
+    config.consensus.version_schedule()?;
+    let chain_identity = config.consensus.chain_identity()?;
+    info!(
+        "chain {} with identity {}",
+        config.consensus.chain,
+        chain_identity.iter().map(|byte| format!("{byte:02x}")).collect::<String>()
+    );
     let config = RwLock::new(config);
 
     debug!("initializing database");
@@ -53,7 +71,7 @@ pub async fn run() -> Result<()> {
     let db = storage::new(database_config).await?;
 
     debug!("initializing blockpool");
-    let mut blockpool = Blockpool::new();
+    let mut blockpool = Blockpool::with_settings(config.read().await.blockpool.clone());
 
     debug!("initializing wallet manager");
     let wallet_manager = WalletManager::new(config.read().await.wallets.clone());
@@ -73,7 +91,7 @@ pub async fn run() -> Result<()> {
     if let Some(matches) = matches.subcommand_matches("admin") {
         debug!("command matched to admin subcommand");
         cli::admin::run(matches, &state).await?;
-        return Ok(());
+        return Ok(ExitStatus::Clean);
     }
 
     let p2p_server = P2PServer::new(state.clone());
@@ -89,15 +107,82 @@ pub async fn run() -> Result<()> {
     let metrics_addr = "0.0.0.0:50048".parse()?;
 
     debug!("starting services");
-    let err = try_join!(
-        p2p_server.start(),
-        rpc_server.start(rpc_addr),
-        metrics_server.start(metrics_addr, matches.is_present("metrics")),
-        http_server.start(http_addr, matches.is_present("web")),
-        rough_time_server.start(rough_time_addr, matches.is_present("roughtime")),
-        blockpool.start(),
-    );
+    let (shutdown_trigger, shutdown) = shutdown::channel();
+    // the blockpool is stopped last so requests that are still running can finish
+    let (blockpool_shutdown_trigger, blockpool_shutdown) = shutdown::channel();
+    let blockpool = tokio::spawn(async move { blockpool.start(blockpool_shutdown).await });
+
+    let services = async {
+        try_join!(
+            p2p_server.start(),
+            rpc_server.start(rpc_addr, shutdown.clone()),
+            metrics_server.start(metrics_addr, matches.is_present("metrics"), shutdown.clone()),
+            http_server.start(http_addr, matches.is_present("web"), shutdown.clone()),
+            rough_time_server.start(rough_time_addr, matches.is_present("roughtime"), shutdown.clone().recv()),
+            mode::start(state.clone(), shutdown.clone()),
+        )
+    };
+    tokio::pin!(services);
+
+    let mut status = tokio::select! {
+        res = &mut services => match res {
+            Ok(_) => {
+                warn!("exiting, all services stopped without a shutdown");
+                ExitStatus::Clean
+            }
+            Err(e) => {
+                error!("exiting, error occurred while starting services: {:?}", e);
+                ExitStatus::ServiceFailed
+            }
+        },
+        signal = shutdown::wait_for_signal() => {
+            match signal {
+                Ok(_) => info!("shutting down"),
+                Err(e) => error!("shutting down, could not listen for signals: {}", e),
+            }
+            shutdown_trigger.trigger();
+            match tokio::time::timeout(SHUTDOWN_TIMEOUT, &mut services).await {
+                Ok(Ok(_)) => ExitStatus::Clean,
+                Ok(Err(e)) => {
+                    error!("error while stopping services: {:?}", e);
+                    ExitStatus::ServiceFailed
+                }
+                Err(_) => {
+                    warn!("services did not stop in time");
+                    ExitStatus::ShutdownTimeout
+                }
+            }
+        }
+    };
+
+    blockpool_shutdown_trigger.trigger();
+    let blockpool_status = match tokio::time::timeout(SHUTDOWN_TIMEOUT, blockpool).await {
+        Ok(Ok(Ok(_))) => ExitStatus::Clean,
+        Ok(Ok(Err(e))) => {
+            error!("error while stopping blockpool: {:?}", e);
+            ExitStatus::ServiceFailed
+        }
+        Ok(Err(e)) => {
+            error!("blockpool crashed: {:?}", e);
+            ExitStatus::ServiceFailed
+        }
+        Err(_) => {
+            warn!("blockpool did not stop in time, pending blocks are lost");
+            ExitStatus::ShutdownTimeout
+        }
+    };
+    if status == ExitStatus::Clean {
+        status = blockpool_status;
+    }
+
+    debug!("flushing database");
+    if let Err(e) = state.db.lock().await.flush().await {
+        error!("error while flushing database: {}", e);
+        if status == ExitStatus::Clean {
+            status = ExitStatus::ServiceFailed;
+        }
+    }
 
-    tracing::error!("exiting, error occurred while starting services: {:?}", err);
-    Ok(())
+    info!("shutdown complete");
+    Ok(status)
 }
@@ -2,5 +2,6 @@ use anyhow::Result;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    champ_node::run().await
+    let status = champ_node::run().await?;
+    std::process::exit(status as i32)
 }
@@ -9,6 +9,8 @@ use hyper::{
 
 use tracing::info;
 
+use crate::shutdown::Shutdown;
+
 use lazy_static::lazy_static;
 use prometheus::{register_int_gauge, Encoder, TextEncoder};
 
@@ -30,7 +32,12 @@ impl MetricsServer {
     pub fn new() -> Self {
         Self {}
     }
-    pub async fn start(&self, addr: SocketAddr, enable: bool) -> Result<(), Box<dyn std::error::Error>> {
+    pub async fn start(
+        &self,
+        addr: SocketAddr,
+        enable: bool,
+        shutdown: Shutdown,
+    ) -> Result<(), Box<dyn std::error::Error>> {
         if !enable {
             return Ok(());
         }
@@ -38,7 +45,7 @@ impl MetricsServer {
         METRICS_HEALTH.set(ServiceStatus::Starting as i64);
 
         let metrics_service = make_service_fn(|_| async { Ok::<_, hyper::Error>(service_fn(serve_req)) });
-        let server = Server::bind(&addr).serve(metrics_service);
+        let server = Server::bind(&addr).serve(metrics_service).with_graceful_shutdown(shutdown.recv());
 
         info!("starting metrics at {}", addr);
 
@@ -0,0 +1,239 @@
+//! What the node does in each consensus mode
+//!
+//! The mode is set in the config and can be changed at runtime with `NodeAdmin.SetMode`. Services that only run in
+//! some modes are started and stopped by [`start`] whenever the mode changes.
+//!
+//! Only light nodes differ in what they store and run. Voting and proposing need wallets with signing keys, relaying
+//! needs p2p and light nodes don't sync block headers yet, so `Prime`, `Validating` and `Observer` nodes run the same
+//! services, see `docs/operators/installation.md`.
+
+use std::time::Duration;
+
+use anyhow::Result;
+use pog_proto::{api::AccountID, rpc::node_admin::Mode};
+use tokio::{sync::watch, task::JoinHandle};
+use tracing::{debug, error, info, warn};
+
+use crate::consensus::prime_delegates::{self, get_prime_delegates};
+use crate::shutdown::{self, Shutdown, ShutdownTrigger};
+use crate::state::ChampStateArc;
+use crate::wallets::WalletInterface;
+
+/// How long a service can take to stop after the mode changed
+const STOP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Subsystems a node runs in a mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// checks that one of the node's wallets is a prime delegate, casting votes is not implemented yet
+    pub vote: bool,
+    /// stores the blocks of every account instead of only the accounts of the node's wallets, without it claims of
+    /// unknown sends are accepted
+    pub all_accounts: bool,
+    /// takes voting power snapshots and selects the prime delegates of every epoch
+    pub prime_delegates: bool,
+}
+
+impl Capabilities {
+    pub fn of(mode: Mode) -> Self {
+        match mode {
+            Mode::Prime => Self {
+                vote: true,
+                all_accounts: true,
+                prime_delegates: true,
+            },
+            // only differ once validating nodes relay blocks
+            Mode::Validating => Self {
+                vote: false,
+                all_accounts: true,
+                prime_delegates: true,
+            },
+            Mode::Observer => Self {
+                vote: false,
+                all_accounts: true,
+                prime_delegates: true,
+            },
+            Mode::Light => Self {
+                vote: false,
+                all_accounts: false,
+                prime_delegates: false,
+            },
+        }
+    }
+}
+
+/// The current mode of the node, services subscribe to it to notice when it changes
+#[derive(Debug)]
+pub struct ModeSwitch {
+    tx: watch::Sender<Mode>,
+    // keeps the channel open, so sending never fails
+    rx: watch::Receiver<Mode>,
+}
+
+impl ModeSwitch {
+    pub fn new(mode: Mode) -> Self {
+        let (tx, rx) = watch::channel(mode);
+        Self {
+            tx,
+            rx,
+        }
+    }
+
+    pub fn current(&self) -> Mode {
+        *self.rx.borrow()
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities::of(self.current())
+    }
+
+    /// Changes the mode, the services of the new mode are started in the background
+    pub fn set(&self, mode: Mode) {
+        let _ = self.tx.send(mode);
+    }
+
+    fn subscribe(&self) -> watch::Receiver<Mode> {
+        self.rx.clone()
+    }
+}
+
+/// Whether the node stores the blocks of an account in its current mode
+pub async fn tracks_account(state: &ChampStateArc, account: &AccountID) -> bool {
+    state.mode.capabilities().all_accounts || state.wallet_manager.read().await.get_wallets().contains_key(account)
+}
+
+/// A service that only runs in some modes
+struct Service {
+    name: &'static str,
+    trigger: ShutdownTrigger,
+    handle: JoinHandle<Result<()>>,
+}
+
+impl Service {
+    fn spawn<F>(name: &'static str, start: impl FnOnce(Shutdown) -> F) -> Self
+    where
+        F: std::future::Future<Output = Result<()>> + Send + 'static,
+    {
+        debug!("starting {name}");
+        let (trigger, shutdown) = shutdown::channel();
+        Self {
+            name,
+            trigger,
+            handle: tokio::spawn(start(shutdown)),
+        }
+    }
+
+    /// Waits until the service finished what it was doing, so it never runs alongside the services of another mode
+    async fn stop(self) {
+        debug!("stopping {}", self.name);
+        self.trigger.trigger();
+        match tokio::time::timeout(STOP_TIMEOUT, self.handle).await {
+            Ok(Ok(Ok(_))) => {}
+            Ok(Ok(Err(e))) => error!("error while stopping {}: {:?}", self.name, e),
+            Ok(Err(e)) => error!("{} crashed: {:?}", self.name, e),
+            Err(_) => warn!("{} did not stop in time", self.name),
+        }
+    }
+}
+
+/// Starts and stops the services of the current mode whenever it changes, until the node shuts down
+pub async fn start(state: ChampStateArc, shutdown: Shutdown) -> Result<()> {
+    let mut modes = state.mode.subscribe();
+    let mut selection: Option<Service> = None;
+
+    let shutdown = shutdown.recv();
+    tokio::pin!(shutdown);
+    loop {
+        let mode = *modes.borrow();
+        info!("running in {:?} mode", mode);
+        let capabilities = Capabilities::of(mode);
+
+        selection = match (capabilities.prime_delegates, selection) {
+            (true, None) => {
+                let state = state.clone();
+                Some(Service::spawn("prime delegate selection", |shutdown| prime_delegates::start(state, shutdown)))
+            }
+            (false, Some(service)) => {
+                service.stop().await;
+                None
+            }
+            (_, selection) => selection,
+        };
+        if capabilities.vote {
+            check_prime_delegate(&state).await;
+        }
+
+        tokio::select! {
+            changed = modes.changed() => if changed.is_err() {
+                break;
+            },
+            _ = &mut shutdown => break,
+        }
+    }
+
+    if let Some(service) = selection {
+        service.stop().await;
+    }
+    Ok(())
+}
+
+/// Prime nodes can only vote with a wallet that is a prime delegate of the current epoch
+async fn check_prime_delegate(state: &ChampStateArc) {
+    let prime_delegates = match get_prime_delegates(state).await {
+        Ok(Some(prime_delegates)) => prime_delegates,
+        _ => return,
+    };
+    let wallet_manager = state.wallet_manager.read().await;
+    if !wallet_manager.get_wallets().keys().any(|account| prime_delegates.get_power(account).is_some()) {
+        warn!("none of the node's wallets is a prime delegate of epoch {}, it can't vote", prime_delegates.epoch);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::prime_delegates::PrimeDelegates;
+    use crate::state::ChampState;
+
+    /// Lets the supervisor and its services react to a mode change
+    async fn settle() {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+
+    async fn selected_epoch(state: &ChampStateArc) -> Option<u64> {
+        get_prime_delegates(state).await.expect("should get prime delegates").map(|selected| selected.epoch)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_mode_switch() {
+        let state = ChampState::mock().await;
+        state.mode.set(Mode::Light);
+        let (trigger, shutdown) = shutdown::channel();
+        let supervisor = tokio::spawn(start(state.clone(), shutdown));
+        assert!(!tracks_account(&state, &[1; 24]).await);
+
+        // light nodes don't select prime delegates
+        tokio::time::sleep(prime_delegates::UPDATE_INTERVAL * 2).await;
+        assert_eq!(selected_epoch(&state).await, None);
+
+        state.mode.set(Mode::Observer);
+        settle().await;
+        assert!(tracks_account(&state, &[1; 24]).await);
+        assert_eq!(selected_epoch(&state).await, Some(prime_delegates::current_epoch()));
+
+        // a running selection would replace the outdated prime delegates within the update interval
+        state.mode.set(Mode::Light);
+        settle().await;
+        let outdated = PrimeDelegates {
+            epoch: 0,
+            delegates: vec![],
+            max_power: 0,
+        };
+        state.db.lock().await.set_prime_delegates(outdated).await.expect("should set prime delegates");
+        tokio::time::sleep(prime_delegates::UPDATE_INTERVAL * 2).await;
+        assert_eq!(selected_epoch(&state).await, Some(0));
+
+        trigger.trigger();
+        supervisor.await.expect("should not panic").expect("should stop");
+    }
+}
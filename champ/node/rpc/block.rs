@@ -144,18 +144,20 @@ impl Block for BlockService {
         }
     }
 
+    // needs the account and pagination fields in pog-proto, see BlockpoolClient::get_pending_blocks
     async fn get_pending_blocks(
         &self,
         _request: tonic::Request<Empty>,
     ) -> Result<tonic::Response<PendingBlockReply>, tonic::Status> {
-        unimplemented!()
+        Err(Status::new(tonic::Code::Unimplemented, "pending blocks can only be requested per account"))
     }
 
+    // needs the account and pagination fields in pog-proto, see BlockpoolClient::get_unacknowledged_txs
     async fn get_unacknowledged_tx(
         &self,
         _request: tonic::Request<Empty>,
     ) -> Result<tonic::Response<UnacknowledgedTxReply>, tonic::Status> {
-        unimplemented!()
+        Err(Status::new(tonic::Code::Unimplemented, "unacknowledged transactions can only be requested per account"))
     }
 
     async fn get_tx_by_id(
@@ -20,6 +20,8 @@ impl NodeAdminService {
 }
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Max number of pending blocks returned at once, the request can't be paginated yet
+const PENDING_BLOCKS_LIMIT: usize = 100;
 
 #[tonic::async_trait]
 impl NodeAdmin for NodeAdminService {
@@ -51,9 +53,19 @@ impl NodeAdmin for NodeAdminService {
 
     async fn get_pending_blocks(
         &self,
-        _request: tonic::Request<Empty>,
+        request: tonic::Request<Empty>,
     ) -> Result<tonic::Response<GetPendingBlocksReply>, tonic::Status> {
-        unimplemented!()
+        debug!("getting pending blocks");
+
+        verify_perms(&request, "admin.read")?;
+        Ok(Response::new(GetPendingBlocksReply {
+            blocks: self
+                .state
+                .blockpool_client
+                .get_pending_blocks(None, 0, PENDING_BLOCKS_LIMIT)
+                .await
+                .map_err(|_| Status::new(tonic::Code::Internal, "could not get pending blocks"))?,
+        }))
     }
 
     async fn get_block_pool_size(
@@ -97,10 +109,12 @@ impl NodeAdmin for NodeAdminService {
 
         verify_perms(&request, "admin.write")?;
         let new_mode = Mode::from_i32(request.into_inner().mode)
-            .ok_or_else(|| Status::new(tonic::Code::Internal, "invalid mode"))?;
+            .ok_or_else(|| Status::new(tonic::Code::InvalidArgument, "invalid mode"))?;
         let mut config = self.state.config.write().await;
         config.consensus.mode = new_mode;
-        config.write().map_err(|_| Status::new(tonic::Code::Internal, "could not update the node name"))?;
+        config.write().map_err(|_| Status::new(tonic::Code::Internal, "could not update the mode"))?;
+        // services of the new mode are started in the background
+        self.state.mode.set(new_mode);
         Ok(Response::new(Empty {}))
     }
 
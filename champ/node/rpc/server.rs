@@ -4,6 +4,7 @@ use crate::rpc::block::{BlockServer, BlockService};
 use crate::rpc::node_admin::{NodeAdminServer, NodeAdminService};
 use crate::rpc::node_user::{NodeUserServer, NodeUserService};
 use crate::rpc::node_wallet_manager::{NodeWalletManagerServer, NodeWalletManagerService};
+use crate::shutdown::Shutdown;
 use crate::state::ChampStateArc;
 use std::{net::SocketAddr, time::Duration};
 
@@ -30,7 +31,8 @@ impl RpcServer {
         }
     }
 
-    pub async fn start(&self, addr: SocketAddr) -> Result<(), Box<dyn std::error::Error>> {
+    /// Serves requests until the node shuts down, requests that are already running are completed first
+    pub async fn start(&self, addr: SocketAddr, shutdown: Shutdown) -> Result<(), Box<dyn std::error::Error>> {
         GRPC_HEALTH.set(ServiceStatus::Starting as i64);
 
         let (public_key, users) = {
@@ -71,7 +73,7 @@ impl RpcServer {
                 .add_service(grpc_web.enable(node_admin_server))
                 .add_service(grpc_web.enable(node_wallet_manager_server))
                 .add_service(grpc_web.enable(node_user))
-                .serve(addr)
+                .serve_with_shutdown(addr, shutdown.recv())
                 .await
             {
                 tracing::error!("error while starting grpc server: {}", e);
@@ -79,7 +81,7 @@ impl RpcServer {
             }
         } else {
             info!("admin service is disabled");
-            if let Err(e) = server.serve(addr).await {
+            if let Err(e) = server.serve_with_shutdown(addr, shutdown.recv()).await {
                 tracing::error!("error while starting grpc server: {}", e);
                 GRPC_HEALTH.set(ServiceStatus::Broken as i64);
             }
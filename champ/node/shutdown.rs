@@ -0,0 +1,90 @@
+use tokio::sync::watch;
+
+/// Exit status of the node process
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+    Clean = 0,
+    /// A service stopped on its own, e.g. because it couldn't bind to its address
+    ServiceFailed = 1,
+    /// Services didn't stop in time after a shutdown was requested
+    ShutdownTimeout = 2,
+}
+
+/// Starts a shutdown of all services listening to the paired [`Shutdown`]
+#[derive(Debug)]
+pub struct ShutdownTrigger {
+    tx: watch::Sender<bool>,
+}
+
+impl ShutdownTrigger {
+    pub fn trigger(&self) {
+        let _ = self.tx.send(true);
+    }
+}
+
+/// Lets a service know when it should stop
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    rx: watch::Receiver<bool>,
+}
+
+impl Shutdown {
+    /// Resolves once a shutdown was triggered
+    ///
+    /// Never resolves if the trigger was dropped without starting a shutdown.
+    pub async fn recv(mut self) {
+        while !*self.rx.borrow() {
+            if self.rx.changed().await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+}
+
+pub fn channel() -> (ShutdownTrigger, Shutdown) {
+    let (tx, rx) = watch::channel(false);
+    (
+        ShutdownTrigger {
+            tx,
+        },
+        Shutdown {
+            rx,
+        },
+    )
+}
+
+/// Waits until the process receives SIGINT (Ctrl-C) or SIGTERM
+pub async fn wait_for_signal() -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            res = tokio::signal::ctrl_c() => res,
+            _ = terminate.recv() => Ok(()),
+        }
+    }
+
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_shutdown() {
+        let (trigger, shutdown) = channel();
+        let waiting = tokio::time::timeout(Duration::from_millis(10), shutdown.clone().recv()).await;
+        assert!(waiting.is_err(), "should wait for the trigger");
+
+        let waiting = tokio::spawn(shutdown.clone().recv());
+        trigger.trigger();
+        waiting.await.expect("should resolve after the trigger");
+        // services started after the trigger stop right away
+        shutdown.recv().await;
+    }
+}
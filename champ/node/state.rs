@@ -2,7 +2,12 @@ use crate::{storage::Database, wallets::WalletManager};
 use std::sync::Arc;
 use tokio::sync::{Mutex, RwLock};
 
-use crate::{blockpool::BlockpoolClient, config::Config};
+use crate::{
+    blockpool::BlockpoolClient,
+    config::Config,
+    consensus::{farming::FarmingCache, voting_power::PowerCache},
+    mode::ModeSwitch,
+};
 
 #[derive(Debug)]
 pub struct ChampState {
@@ -10,6 +15,9 @@ pub struct ChampState {
     pub config: RwLock<Config>,
     pub wallet_manager: RwLock<WalletManager>,
     pub blockpool_client: BlockpoolClient,
+    pub power_cache: PowerCache,
+    pub farming_cache: FarmingCache,
+    pub mode: ModeSwitch,
 }
 
 pub struct ChampStateArgs {
@@ -20,18 +28,22 @@ pub struct ChampStateArgs {
 }
 
 impl ChampState {
-    pub fn new(args: ChampStateArgs) -> ChampStateArc {
+    pub fn new(mut args: ChampStateArgs) -> ChampStateArc {
+        let mode = args.config.get_mut().consensus.mode;
         Arc::new(Self {
             db: Mutex::new(args.db),
             config: args.config,
             wallet_manager: args.wallet_manager,
             blockpool_client: args.blockpool_client,
+            power_cache: PowerCache::default(),
+            farming_cache: FarmingCache::default(),
+            mode: ModeSwitch::new(mode),
         })
     }
 
     #[cfg(test)]
     pub async fn mock() -> ChampStateArc {
-        use crate::{blockpool::Blockpool, storage};
+        use crate::{blockpool::Blockpool, shutdown, storage};
 
         let mut pool = Blockpool::new();
         let blockpool_client = pool.get_client();
@@ -46,15 +58,20 @@ impl ChampState {
             .unwrap(),
         );
 
+        let config = Config::default();
         let state = Arc::new(Self {
             db,
-            config: RwLock::new(Config::default()),
+            mode: ModeSwitch::new(config.consensus.mode),
+            config: RwLock::new(config),
             wallet_manager: RwLock::new(WalletManager::mock()),
             blockpool_client,
+            power_cache: PowerCache::default(),
+            farming_cache: FarmingCache::default(),
         });
 
         pool.add_state(state.clone());
-        tokio::spawn(async move { pool.start().await });
+        let (_, shutdown) = shutdown::channel();
+        tokio::spawn(async move { pool.start(shutdown).await });
         state
     }
 }
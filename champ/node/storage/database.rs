@@ -3,6 +3,8 @@ use std::fmt::Debug;
 use anyhow::Result;
 use async_trait::async_trait;
 use pog_proto::api::{self};
+
+use crate::consensus::{prime_delegates::PrimeDelegates, snapshot::PowerSnapshot};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
@@ -120,7 +122,8 @@ pub trait Database: Send + Sync {
 
     /// Finds the latest block for a given address before a given date
     ///
-    /// Set limit to 0 to keep looking until an accounts first transaction
+    /// Set limit to 0 to keep looking until an accounts first transaction.
+    /// Returns `None` if there is no such block, or only blocks before the limit
     async fn get_latest_block_by_account_before(
         &self,
         account_id: api::AccountID,
@@ -141,9 +144,52 @@ pub trait Database: Send + Sync {
     // Adds a new block to the database
     async fn add_block(&mut self, block: api::SignedBlock) -> Result<(), DatabaseError>;
 
+    // Removes the latest block of an account and reverts everything it changed, e.g. when it lost a vote.
+    // Fails if another block already claimed one of its sends.
+    async fn remove_block(&mut self, block_id: api::BlockID) -> Result<(), DatabaseError>;
+
+    // Gets the id of the block containing a transaction
+    async fn get_transaction_block_id(
+        &self,
+        transaction_id: api::TransactionID,
+    ) -> Result<Option<api::BlockID>, DatabaseError>;
+
     // Get the transaction id claiming a send transaction
     async fn get_send_recipient(
         &self,
         send_transaction_id: api::TransactionID,
     ) -> Result<Option<api::TransactionID>, DatabaseError>;
+
+    // Finds the sends to an account that have not been claimed yet together with their amounts
+    async fn get_receivable(
+        &self,
+        account_id: api::AccountID,
+    ) -> Result<Vec<(api::TransactionID, u64)>, DatabaseError>;
+
+    // Stores blocks that are not confirmed yet so they survive a restart, after the already stored blocks
+    async fn add_pending_blocks(&mut self, blocks: Vec<api::SignedBlock>) -> Result<(), DatabaseError>;
+
+    // Gets the stored unconfirmed blocks together with their index in the order they were stored
+    async fn get_pending_blocks(&self) -> Result<Vec<(u64, api::SignedBlock)>, DatabaseError>;
+
+    // Removes a stored unconfirmed block once it is back in the blockpool
+    async fn remove_pending_block(&mut self, index: u64) -> Result<(), DatabaseError>;
+
+    // Lists every account that has at least one block
+    async fn get_accounts(&self) -> Result<Vec<api::AccountID>, DatabaseError>;
+
+    // Gets the prime delegates of the latest epoch they were selected for
+    async fn get_prime_delegates(&self) -> Result<Option<PrimeDelegates>, DatabaseError>;
+
+    // Replaces the prime delegates with the ones of a new epoch
+    async fn set_prime_delegates(&mut self, prime_delegates: PrimeDelegates) -> Result<(), DatabaseError>;
+
+    // Gets the voting power snapshot taken at the start of an epoch
+    async fn get_power_snapshot(&self, epoch: u64) -> Result<Option<PowerSnapshot>, DatabaseError>;
+
+    // Stores the voting power snapshot of an epoch
+    async fn set_power_snapshot(&mut self, snapshot: PowerSnapshot) -> Result<(), DatabaseError>;
+
+    // Writes all buffered changes to disk
+    async fn flush(&self) -> Result<(), DatabaseError>;
 }
@@ -1,5 +1,6 @@
 use std::convert::TryInto;
 
+use crate::consensus::{prime_delegates::PrimeDelegates, snapshot::PowerSnapshot};
 use crate::storage::{Database, DatabaseConfig, DatabaseError};
 use anyhow::Result;
 use async_trait::async_trait;
@@ -9,13 +10,14 @@ use sled::Transactional;
 
 #[derive(Debug)]
 pub struct SledDB {
-    // db: sled::Db,
-    // pending_blocks: sled::Tree,
+    db: sled::Db,
+    pending_blocks: sled::Tree,
     blocks: sled::Tree,
     accounts: sled::Tree,
     transactions: sled::Tree,
     claims: sled::Tree,
-    // meta: sled::Tree,
+    receivable: sled::Tree,
+    meta: sled::Tree,
 }
 
 impl SledDB {
@@ -29,13 +31,12 @@ impl SledDB {
         }
 
         let db: sled::Db = sled_cfg.open()?;
-        // let pending_blocks = db.open_tree("pending_blocks")?;
+        // pending_blocks contains the blocks of the blockpool while the node is shut down
+        let pending_blocks = db.open_tree("pending_blocks")?;
         // pending_blocks contain:
         //
-        // key: generate_id
+        // key: index
         // val: block proto
-        // // this is used after e.g a server crash to recover the pending log
-        // // these are atomically moved to blocks once accepted
 
         // accounts provides some convenient pointers to data relevant to an account
         let accounts = db.open_tree("accounts")?;
@@ -44,8 +45,11 @@ impl SledDB {
         // key: account_id + "_last_blk"
         // val: latest block id
         //
-        // key: account_id + "_rep"
+        // key: "rep_" + account_id
         // val: representative account_id
+        //
+        // key: "delegates_" + representative account_id + account_id
+        // val: empty, indexes the accounts delegating to a representative
 
         // claims provides some convenient pointers to data relevant to claim transactions
         let claims = db.open_tree("claims")?;
@@ -53,6 +57,12 @@ impl SledDB {
         // key: send_transaction_id
         // val: recieve_transaction_id
 
+        // receivable indexes send transactions that have not been claimed yet by their receiver
+        let receivable = db.open_tree("receivable")?;
+        // receivable contains
+        // key: receiver account_id + send_transaction_id
+        // val: amount
+
         let blocks = db.open_tree("blocks")?;
         // blocks contain:
         //
@@ -75,16 +85,25 @@ impl SledDB {
         // key: "by_blk_id_" + block_id + "block_index"
         // val: transaction proto
 
-        // let meta = db.open_tree("meta")?;
+        // meta contains data about the network as a whole
+        let meta = db.open_tree("meta")?;
+        // meta contains:
+        //
+        // key: "prime_delegates"
+        // val: prime delegates proto of the latest epoch
+        //
+        // key: "power_" + epoch
+        // val: voting power snapshot proto
 
         Ok(Self {
-            // db,
-            // pending_blocks,
+            db,
+            pending_blocks,
             blocks,
             accounts,
             transactions,
             claims,
-            // meta,
+            receivable,
+            meta,
         })
     }
 }
@@ -114,8 +133,7 @@ impl Database for SledDB {
             .blocks
             .get(block_key)
             .map_err(|e| DatabaseError::Specific(e.to_string()))?
-            .ok_or(DatabaseError::BlockNotFound)
-            .map_err(|e| DatabaseError::Specific(e.to_string()))?;
+            .ok_or(DatabaseError::BlockNotFound)?;
 
         api::SignedBlock::decode(&*block.to_vec()).map_err(|e| DatabaseError::Specific(e.to_string()))
     }
@@ -137,6 +155,26 @@ impl Database for SledDB {
         api::Transaction::decode(&*transaction.to_vec()).map_err(|e| DatabaseError::Specific(e.to_string()))
     }
 
+    async fn get_transaction_block_id(
+        &self,
+        transaction_id: api::TransactionID,
+    ) -> Result<Option<api::BlockID>, DatabaseError> {
+        let mut tx_key = b"blk_by_id_".to_vec();
+        tx_key.append(&mut transaction_id.to_vec());
+
+        let block_id = self.transactions.get(tx_key).map_err(|e| DatabaseError::Specific(e.to_string()))?;
+        match block_id {
+            Some(block_id) => {
+                let block_id: BlockID = block_id
+                    .to_vec()
+                    .try_into()
+                    .map_err(|_| DatabaseError::Specific("invalid block id".to_string()))?;
+                Ok(Some(block_id))
+            }
+            None => Ok(None),
+        }
+    }
+
     async fn get_latest_block_by_account(
         &self,
         account_id: api::AccountID,
@@ -148,8 +186,7 @@ impl Database for SledDB {
             .accounts
             .get(last_block_key)
             .map_err(|e| DatabaseError::Specific(e.to_string()))?
-            .ok_or(DatabaseError::BlockNotFound)
-            .map_err(|e| DatabaseError::Specific(e.to_string()))?
+            .ok_or(DatabaseError::NoLastBlock)?
             .to_vec()
             .try_into()
             .map_err(|_| DatabaseError::BlockNotFound)?;
@@ -174,8 +211,8 @@ impl Database for SledDB {
             .map_err(|_| DatabaseError::Specific("account ID could not be generated".to_string()))?;
 
         let res: sled::transaction::TransactionResult<()> =
-            (&self.accounts, &self.blocks, &self.transactions, &self.claims).transaction(
-                |(accounts, blocks, transactions, claims)| {
+            (&self.accounts, &self.blocks, &self.transactions, &self.claims, &self.receivable).transaction(
+                |(accounts, blocks, transactions, claims, receivable)| {
                     let mut block_key = b"by_id_".to_vec();
                     block_key.append(&mut block_id.to_vec());
 
@@ -208,13 +245,24 @@ impl Database for SledDB {
                             api::transaction::Data::TxDelegate(tx) => {
                                 let mut account_rep_key = b"rep_".to_vec();
                                 account_rep_key.append(&mut account_id.to_vec());
+                                if let Some(previous) = accounts.get(&account_rep_key)? {
+                                    accounts.remove(delegates_key(&previous, &account_id))?;
+                                }
+                                accounts.insert(delegates_key(&tx.representative, &account_id), Vec::<u8>::new())?;
                                 accounts.insert(account_rep_key, tx.representative)?;
                             }
-                            // Set claims
+                            // Set claims, only the receiver of a send can claim it
                             api::transaction::Data::TxClaim(tx) => {
+                                let mut receivable_key = account_id.to_vec();
+                                receivable_key.append(&mut tx.send_transaction_id.clone());
+                                receivable.remove(receivable_key)?;
                                 claims.insert(tx.send_transaction_id, transaction_id.to_vec())?;
                             }
-                            _ => {}
+                            api::transaction::Data::TxSend(tx) => {
+                                let mut receivable_key = tx.receiver;
+                                receivable_key.append(&mut transaction_id.to_vec());
+                                receivable.insert(receivable_key, &tx.amount.to_be_bytes())?;
+                            }
                         };
 
                         let tx = tx.encode_to_vec();
@@ -244,6 +292,258 @@ impl Database for SledDB {
         res.map_err(|_| DatabaseError::DBInsertFailed(line!()))
     }
 
+    async fn remove_block(&mut self, block_id: api::BlockID) -> Result<(), DatabaseError> {
+        let block = self.get_block_by_id(block_id).await?;
+        let block_data = block.data.clone().ok_or_else(|| DatabaseError::Specific("block has no data".to_string()))?;
+        let account_id = encoding::account::generate_account_address(block.public_key.clone())
+            .map_err(|_| DatabaseError::Specific("account ID could not be generated".to_string()))?;
+
+        let latest_id = self
+            .get_latest_block_by_account(account_id)
+            .await?
+            .get_id()
+            .map_err(|e| DatabaseError::Specific(e.to_string()))?;
+        if latest_id != block_id {
+            return Err(DatabaseError::Specific("only the latest block of an account can be removed".to_string()));
+        }
+
+        let mut transaction_ids = Vec::with_capacity(block_data.transactions.len());
+        // claimed sends become receivable again
+        let mut restored_receivable = Vec::new();
+        let mut changes_representative = false;
+        for tx in &block_data.transactions {
+            let transaction_id = tx.get_id(block_id).map_err(|e| DatabaseError::Specific(e.to_string()))?;
+            match &tx.data {
+                Some(api::transaction::Data::TxSend(_)) => {
+                    if self.get_send_recipient(transaction_id).await?.is_some() {
+                        return Err(DatabaseError::Specific("send transaction has already been claimed".to_string()));
+                    }
+                }
+                Some(api::transaction::Data::TxClaim(claim)) => {
+                    let send_id: api::TransactionID = claim
+                        .send_transaction_id
+                        .clone()
+                        .try_into()
+                        .map_err(|_| DatabaseError::Specific("invalid transaction id".to_string()))?;
+                    let amount = match self.get_transaction_by_id(send_id).await?.data {
+                        Some(api::transaction::Data::TxSend(send)) => send.amount,
+                        _ => return Err(DatabaseError::Specific("claimed transaction is not a send".to_string())),
+                    };
+                    let mut receivable_key = account_id.to_vec();
+                    receivable_key.append(&mut send_id.to_vec());
+                    restored_receivable.push((receivable_key, amount));
+                }
+                Some(api::transaction::Data::TxDelegate(_)) => changes_representative = true,
+                None => {}
+            }
+            transaction_ids.push(transaction_id);
+        }
+
+        // the representative set by the newest older block, which is active again after the removal
+        let mut previous_representative = None;
+        if changes_representative {
+            for height in (0..block_data.height).rev() {
+                let older = match self.get_block_by_height(account_id, &height).await? {
+                    Some(block) => block,
+                    None => continue,
+                };
+                let delegate =
+                    older.data.into_iter().flat_map(|data| data.transactions).rev().find_map(|tx| match tx.data {
+                        Some(api::transaction::Data::TxDelegate(tx)) => Some(tx.representative),
+                        _ => None,
+                    });
+                if delegate.is_some() {
+                    previous_representative = delegate;
+                    break;
+                }
+            }
+        }
+
+        let res: sled::transaction::TransactionResult<()> =
+            (&self.accounts, &self.blocks, &self.transactions, &self.claims, &self.receivable).transaction(
+                |(accounts, blocks, transactions, claims, receivable)| {
+                    let mut block_key = b"by_id_".to_vec();
+                    block_key.append(&mut block_id.to_vec());
+
+                    let mut block_by_acc_key = b"by_acc_".to_vec();
+                    block_by_acc_key.append(&mut account_id.to_vec());
+                    block_by_acc_key.append(&mut b"_".to_vec());
+                    block_by_acc_key.append(&mut block_data.height.to_be_bytes().to_vec());
+
+                    // Set the previous block as latest block
+                    let mut account_key = account_id.to_vec();
+                    account_key.append(&mut b"_last_blk".to_vec());
+                    if block_data.previous.is_empty() {
+                        accounts.remove(account_key)?;
+                    } else {
+                        accounts.insert(account_key, block_data.previous.clone())?;
+                    }
+
+                    // Restore representative
+                    if changes_representative {
+                        let mut account_rep_key = b"rep_".to_vec();
+                        account_rep_key.append(&mut account_id.to_vec());
+                        if let Some(representative) = accounts.get(&account_rep_key)? {
+                            accounts.remove(delegates_key(&representative, &account_id))?;
+                        }
+                        match &previous_representative {
+                            Some(representative) => {
+                                accounts.insert(delegates_key(representative, &account_id), Vec::<u8>::new())?;
+                                accounts.insert(account_rep_key, representative.clone())?
+                            }
+                            None => accounts.remove(account_rep_key)?,
+                        };
+                    }
+
+                    // Remove Block
+                    blocks.remove(block_key)?;
+                    blocks.remove(block_by_acc_key)?;
+
+                    // Remove Block Transactions
+                    for (i, (tx, transaction_id)) in block_data.transactions.iter().zip(&transaction_ids).enumerate() {
+                        match &tx.data {
+                            Some(api::transaction::Data::TxClaim(tx)) => {
+                                claims.remove(tx.send_transaction_id.clone())?;
+                            }
+                            Some(api::transaction::Data::TxSend(tx)) => {
+                                let mut receivable_key = tx.receiver.clone();
+                                receivable_key.append(&mut transaction_id.to_vec());
+                                receivable.remove(receivable_key)?;
+                            }
+                            _ => {}
+                        };
+
+                        let mut tx_key = b"by_id_".to_vec();
+                        tx_key.append(&mut transaction_id.to_vec());
+                        transactions.remove(tx_key)?;
+
+                        let mut tx_key = b"blk_by_id_".to_vec();
+                        tx_key.append(&mut transaction_id.to_vec());
+                        transactions.remove(tx_key)?;
+
+                        let mut tx_key = b"by_blk_id_".to_vec();
+                        tx_key.append(&mut block_id.to_vec());
+                        tx_key.append(&mut i.to_be_bytes().into());
+                        transactions.remove(tx_key)?;
+                    }
+                    for (receivable_key, amount) in &restored_receivable {
+                        receivable.insert(receivable_key.clone(), &amount.to_be_bytes())?;
+                    }
+
+                    Ok(())
+                },
+            );
+
+        res.map_err(|_| DatabaseError::DBInsertFailed(line!()))
+    }
+
+    async fn get_receivable(
+        &self,
+        account_id: api::AccountID,
+    ) -> Result<Vec<(api::TransactionID, u64)>, DatabaseError> {
+        self.receivable
+            .scan_prefix(account_id)
+            .map(|entry| {
+                let (key, amount) = entry.map_err(|e| DatabaseError::Specific(e.to_string()))?;
+                let send_id: api::TransactionID = key[account_id.len()..]
+                    .try_into()
+                    .map_err(|_| DatabaseError::Specific("invalid transaction id".to_string()))?;
+                let amount: [u8; 8] =
+                    amount.as_ref().try_into().map_err(|_| DatabaseError::Specific("invalid amount".to_string()))?;
+                Ok((send_id, u64::from_be_bytes(amount)))
+            })
+            .collect()
+    }
+
+    async fn add_pending_blocks(&mut self, blocks: Vec<api::SignedBlock>) -> Result<(), DatabaseError> {
+        let last = self.pending_blocks.last().map_err(|e| DatabaseError::Specific(e.to_string()))?;
+        let next_index = match last {
+            Some((key, _)) => pending_block_index(&key)? + 1,
+            None => 0,
+        };
+
+        let mut batch = sled::Batch::default();
+        for (index, block) in (next_index..).zip(blocks.iter()) {
+            batch.insert(&index.to_be_bytes(), block.encode_to_vec());
+        }
+        self.pending_blocks.apply_batch(batch).map_err(|e| DatabaseError::Specific(e.to_string()))
+    }
+
+    async fn get_pending_blocks(&self) -> Result<Vec<(u64, api::SignedBlock)>, DatabaseError> {
+        self.pending_blocks
+            .iter()
+            .map(|entry| {
+                let (key, block) = entry.map_err(|e| DatabaseError::Specific(e.to_string()))?;
+                let block = api::SignedBlock::decode(&*block).map_err(|e| DatabaseError::Specific(e.to_string()))?;
+                Ok((pending_block_index(&key)?, block))
+            })
+            .collect()
+    }
+
+    async fn remove_pending_block(&mut self, index: u64) -> Result<(), DatabaseError> {
+        self.pending_blocks.remove(index.to_be_bytes()).map_err(|e| DatabaseError::Specific(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn get_accounts(&self) -> Result<Vec<api::AccountID>, DatabaseError> {
+        let mut accounts = Vec::new();
+        for key in self.accounts.iter().keys() {
+            let key = key.map_err(|e| DatabaseError::Specific(e.to_string()))?;
+            // every account with a block has a pointer to its latest block
+            if let Some(account_id) = key.strip_suffix(b"_last_blk") {
+                let account_id: AccountID =
+                    account_id.try_into().map_err(|_| DatabaseError::Specific("invalid account id".to_string()))?;
+                accounts.push(account_id);
+            }
+        }
+        Ok(accounts)
+    }
+
+    async fn get_prime_delegates(&self) -> Result<Option<PrimeDelegates>, DatabaseError> {
+        let prime_delegates = self.meta.get(b"prime_delegates").map_err(|e| DatabaseError::Specific(e.to_string()))?;
+        match prime_delegates {
+            Some(prime_delegates) => {
+                PrimeDelegates::decode(&*prime_delegates).map(Some).map_err(|e| DatabaseError::Specific(e.to_string()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn set_prime_delegates(&mut self, prime_delegates: PrimeDelegates) -> Result<(), DatabaseError> {
+        self.meta
+            .insert(b"prime_delegates", prime_delegates.encode_to_vec())
+            .map_err(|e| DatabaseError::Specific(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn get_power_snapshot(&self, epoch: u64) -> Result<Option<PowerSnapshot>, DatabaseError> {
+        let mut snapshot_key = b"power_".to_vec();
+        snapshot_key.append(&mut epoch.to_be_bytes().to_vec());
+
+        let snapshot = self.meta.get(snapshot_key).map_err(|e| DatabaseError::Specific(e.to_string()))?;
+        match snapshot {
+            Some(snapshot) => {
+                PowerSnapshot::decode(&*snapshot).map(Some).map_err(|e| DatabaseError::Specific(e.to_string()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn set_power_snapshot(&mut self, snapshot: PowerSnapshot) -> Result<(), DatabaseError> {
+        let mut snapshot_key = b"power_".to_vec();
+        snapshot_key.append(&mut snapshot.epoch.to_be_bytes().to_vec());
+
+        self.meta
+            .insert(snapshot_key, snapshot.encode_to_vec())
+            .map_err(|e| DatabaseError::Specific(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<(), DatabaseError> {
+        self.db.flush_async().await.map_err(|e| DatabaseError::Specific(e.to_string()))?;
+        Ok(())
+    }
+
     async fn get_block_by_height(
         &self,
         account_id: api::AccountID,
@@ -273,10 +573,10 @@ impl Database for SledDB {
     }
 
     async fn get_account_delegate(&self, account_id: api::AccountID) -> Result<Option<api::AccountID>, DatabaseError> {
-        let mut last_block_key = account_id.to_vec();
-        last_block_key.append(&mut b"_rep".to_vec());
+        let mut account_rep_key = b"rep_".to_vec();
+        account_rep_key.append(&mut account_id.to_vec());
 
-        let delegate_id = self.accounts.get(last_block_key).map_err(|e| DatabaseError::Specific(e.to_string()))?;
+        let delegate_id = self.accounts.get(account_rep_key).map_err(|e| DatabaseError::Specific(e.to_string()))?;
         let delegate_id: AccountID = match delegate_id {
             Some(delegate) => {
                 delegate.to_vec().try_into().map_err(|_| DatabaseError::Specific("invalid account id".to_string()))?
@@ -287,38 +587,20 @@ impl Database for SledDB {
         Ok(Some(delegate_id))
     }
 
-    // TODO: THIS IS NOT OPTIMIZED FOR PERFORMANCE AND BASED ON
-    // OUR MOCK DATABASE CODE! BEWARE! HERE BE DRAGONS!
     async fn get_delegates_by_account(
         &self,
         account_id: api::AccountID,
     ) -> Result<Vec<api::AccountID>, DatabaseError> {
-        let mut delegated_accounts = std::collections::HashSet::new();
-
-        let stuff = self.transactions.scan_prefix(b"blk_by_id_").rev().filter_map(|res| {
-            let (key, value) = res.ok()?;
-            let tx = api::Transaction::decode(&*value.to_vec()).ok()?;
-            let blk_id: BlockID = key[10..].try_into().ok()?;
-            Some((blk_id, tx))
-        });
-
-        for (blk_id, tx) in stuff {
-            let blk = self.get_block_by_id(blk_id).await?;
-            let tx_acc = encoding::account::generate_account_address(blk.public_key.clone())
-                .map_err(|_| DatabaseError::Specific("account ID could not be generated".to_string()))?;
-
-            if let Some(api::transaction::Data::TxDelegate(delegate_tx)) = &tx.data {
-                if delegate_tx.representative == account_id {
-                    // only the latest transaction counts per account
-                    if delegated_accounts.contains(&tx_acc) {
-                        continue;
-                    }
-                    delegated_accounts.insert(tx_acc);
-                }
-            }
+        let prefix = delegates_key(&account_id, &[]);
+        let mut delegated_accounts = Vec::new();
+        for entry in self.accounts.scan_prefix(&prefix) {
+            let (key, _) = entry.map_err(|e| DatabaseError::Specific(e.to_string()))?;
+            let delegate: AccountID = key[prefix.len()..]
+                .try_into()
+                .map_err(|_| DatabaseError::Specific("invalid account id".to_string()))?;
+            delegated_accounts.push(delegate);
         }
-
-        Ok(delegated_accounts.into_iter().collect())
+        Ok(delegated_accounts)
     }
 
     // TODO: THIS IS NOT OPTIMIZED FOR PERFORMANCE AND BASED ON
@@ -329,25 +611,44 @@ impl Database for SledDB {
         unix_from: u64,
         unix_limit: u64,
     ) -> Result<Option<api::SignedBlock>, DatabaseError> {
-        self.blocks
-            .scan_prefix(b"by_id_")
-            // reverse to make it faster for newer blocks
-            .rev()
-            .find_map(|res| {
-                let (_, value) = res.ok()?;
-                let block = api::SignedBlock::decode(&*value.to_vec()).ok()?;
-                let block_account_id = encoding::account::generate_account_address(block.public_key.clone()).ok()?;
-
-                if block_account_id == account_id {
-                    if block.timestamp < unix_limit {
-                        return Some(None);
-                    }
-                    if block.timestamp < unix_from {
-                        return Some(Some(block));
-                    }
-                }
-                None
-            })
-            .ok_or(DatabaseError::Unknown)
+        let mut prefix = b"by_acc_".to_vec();
+        prefix.append(&mut account_id.to_vec());
+        prefix.append(&mut b"_".to_vec());
+
+        // heights are stored big endian, so reversing goes from the latest block to the first one
+        for entry in self.blocks.scan_prefix(prefix).rev() {
+            let (_, block_id) = entry.map_err(|e| DatabaseError::Specific(e.to_string()))?;
+
+            let mut block_key = b"by_id_".to_vec();
+            block_key.append(&mut block_id.to_vec());
+            let block = self
+                .blocks
+                .get(block_key)
+                .map_err(|e| DatabaseError::Specific(e.to_string()))?
+                .ok_or(DatabaseError::BlockNotFound)?;
+            let block =
+                api::SignedBlock::decode(&*block.to_vec()).map_err(|e| DatabaseError::Specific(e.to_string()))?;
+
+            if block.timestamp < unix_limit {
+                return Ok(None);
+            }
+            if block.timestamp < unix_from {
+                return Ok(Some(block));
+            }
+        }
+        Ok(None)
     }
 }
+
+fn delegates_key(representative: &[u8], account_id: &[u8]) -> Vec<u8> {
+    let mut key = b"delegates_".to_vec();
+    key.extend_from_slice(representative);
+    key.extend_from_slice(account_id);
+    key
+}
+
+fn pending_block_index(key: &[u8]) -> Result<u64, DatabaseError> {
+    let index: [u8; 8] =
+        key.try_into().map_err(|_| DatabaseError::Specific("invalid pending block index".to_string()))?;
+    Ok(u64::from_be_bytes(index))
+}
@@ -1,3 +1,4 @@
+use crate::consensus::{prime_delegates::PrimeDelegates, snapshot::PowerSnapshot};
 use crate::storage::{Database, DatabaseConfig, DatabaseError};
 use anyhow::Result;
 use async_trait::async_trait;
@@ -53,6 +54,11 @@ impl Sql {
     }
 }
 
+/// Methods the backend can't store data for yet fail instead of panicking, reads return nothing stored
+fn unsupported(method: &str) -> DatabaseError {
+    DatabaseError::Specific(format!("{method} is unsupported by database backend"))
+}
+
 #[async_trait]
 impl Database for Sql {
     async fn get_block_by_id(&self, _block_id: api::BlockID) -> Result<api::SignedBlock, DatabaseError> {
@@ -114,4 +120,58 @@ impl Database for Sql {
     ) -> Result<Option<api::TransactionID>, DatabaseError> {
         unimplemented!("method unsupported by database backend")
     }
+
+    async fn get_receivable(
+        &self,
+        _account_id: api::AccountID,
+    ) -> Result<Vec<(api::TransactionID, u64)>, DatabaseError> {
+        Err(unsupported("get_receivable"))
+    }
+
+    async fn add_pending_blocks(&mut self, _blocks: Vec<api::SignedBlock>) -> Result<(), DatabaseError> {
+        Err(unsupported("add_pending_blocks"))
+    }
+
+    async fn remove_block(&mut self, _block_id: api::BlockID) -> Result<(), DatabaseError> {
+        Err(unsupported("remove_block"))
+    }
+
+    async fn get_pending_blocks(&self) -> Result<Vec<(u64, api::SignedBlock)>, DatabaseError> {
+        Ok(Vec::new())
+    }
+
+    async fn remove_pending_block(&mut self, _index: u64) -> Result<(), DatabaseError> {
+        Err(unsupported("remove_pending_block"))
+    }
+
+    async fn get_transaction_block_id(
+        &self,
+        _transaction_id: api::TransactionID,
+    ) -> Result<Option<api::BlockID>, DatabaseError> {
+        Err(unsupported("get_transaction_block_id"))
+    }
+
+    async fn get_accounts(&self) -> Result<Vec<api::AccountID>, DatabaseError> {
+        Err(unsupported("get_accounts"))
+    }
+
+    async fn get_prime_delegates(&self) -> Result<Option<PrimeDelegates>, DatabaseError> {
+        Ok(None)
+    }
+
+    async fn set_prime_delegates(&mut self, _prime_delegates: PrimeDelegates) -> Result<(), DatabaseError> {
+        Err(unsupported("set_prime_delegates"))
+    }
+
+    async fn get_power_snapshot(&self, _epoch: u64) -> Result<Option<PowerSnapshot>, DatabaseError> {
+        Ok(None)
+    }
+
+    async fn set_power_snapshot(&mut self, _snapshot: PowerSnapshot) -> Result<(), DatabaseError> {
+        Err(unsupported("set_power_snapshot"))
+    }
+
+    async fn flush(&self) -> Result<(), DatabaseError> {
+        Ok(())
+    }
 }
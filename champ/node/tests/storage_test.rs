@@ -1,4 +1,9 @@
-use pog_proto::api::{signed_block::BlockData, SignedBlock};
+use encoding::account::generate_account_address;
+use pog_proto::api::{
+    signed_block::BlockData,
+    transaction::{Data, TxClaim, TxSend},
+    SignedBlock, Transaction,
+};
 
 use common::storage::TestStorage;
 mod common;
@@ -25,6 +30,121 @@ async fn test_add_block() {
     assert_eq!(block_res, block);
 }
 
+#[tokio::test]
+async fn test_get_accounts() {
+    let mut db = TestStorage::new().await.db;
+    assert!(db.get_accounts().await.expect("should return accounts").is_empty());
+
+    let block = SignedBlock {
+        public_key: b"account".to_vec(),
+        data: Some(TestStorage::mock_blockdata(0, 0, &[], vec![])),
+        ..Default::default()
+    };
+    db.add_block(block).await.expect("should add block to database");
+
+    let account = generate_account_address(b"account".to_vec()).expect("should generate address");
+    assert_eq!(db.get_accounts().await.expect("should return accounts"), vec![account]);
+}
+
+#[tokio::test]
+async fn test_receivable() {
+    let mut db = TestStorage::new().await.db;
+    let receiver = generate_account_address(b"receiver".to_vec()).expect("should generate address");
+    let send = |amount| Transaction {
+        data: Some(Data::TxSend(TxSend {
+            receiver: receiver.to_vec(),
+            amount,
+            data: vec![],
+        })),
+    };
+
+    let send_block = SignedBlock {
+        public_key: b"sender".to_vec(),
+        data: Some(TestStorage::mock_blockdata(70, 0, &[], vec![send(10), send(20)])),
+        ..Default::default()
+    };
+    let send_block_id = send_block.get_id().expect("should generate block id");
+    let first_id = send(10).get_id(send_block_id).expect("should generate tx id");
+    let second_id = send(20).get_id(send_block_id).expect("should generate tx id");
+    db.add_block(send_block).await.expect("should add block to database");
+
+    let mut receivable = db.get_receivable(receiver).await.expect("should return receivable");
+    receivable.sort();
+    let mut expected = vec![(first_id, 10), (second_id, 20)];
+    expected.sort();
+    assert_eq!(receivable, expected);
+
+    let claim_block = SignedBlock {
+        public_key: b"receiver".to_vec(),
+        data: Some(TestStorage::mock_blockdata(
+            10,
+            0,
+            &[],
+            vec![Transaction {
+                data: Some(Data::TxClaim(TxClaim {
+                    send_transaction_id: first_id.to_vec(),
+                })),
+            }],
+        )),
+        ..Default::default()
+    };
+    db.add_block(claim_block).await.expect("should add block to database");
+
+    let receivable = db.get_receivable(receiver).await.expect("should return receivable");
+    assert_eq!(receivable, vec![(second_id, 20)]);
+}
+
+#[tokio::test]
+async fn test_remove_block() {
+    let mut db = TestStorage::new().await.db;
+    let receiver = generate_account_address(b"receiver".to_vec()).expect("should generate address");
+    let send = Transaction {
+        data: Some(Data::TxSend(TxSend {
+            receiver: receiver.to_vec(),
+            amount: 10,
+            data: vec![],
+        })),
+    };
+
+    let send_block = SignedBlock {
+        public_key: b"sender".to_vec(),
+        data: Some(TestStorage::mock_blockdata(90, 0, &[], vec![send.clone()])),
+        ..Default::default()
+    };
+    let send_block_id = send_block.get_id().expect("should generate block id");
+    let send_id = send.get_id(send_block_id).expect("should generate tx id");
+    db.add_block(send_block).await.expect("should add block to database");
+
+    let claim_block = SignedBlock {
+        public_key: b"receiver".to_vec(),
+        data: Some(TestStorage::mock_blockdata(
+            10,
+            0,
+            &[],
+            vec![Transaction {
+                data: Some(Data::TxClaim(TxClaim {
+                    send_transaction_id: send_id.to_vec(),
+                })),
+            }],
+        )),
+        ..Default::default()
+    };
+    let claim_block_id = claim_block.get_id().expect("should generate block id");
+    db.add_block(claim_block).await.expect("should add block to database");
+
+    // the send is claimed, so removing its block would leave a dangling claim
+    assert!(db.remove_block(send_block_id).await.is_err());
+
+    db.remove_block(claim_block_id).await.expect("should remove block");
+    assert!(db.get_block_by_id(claim_block_id).await.is_err());
+    assert!(db.get_send_recipient(send_id).await.expect("should return recipient").is_none());
+    assert_eq!(db.get_receivable(receiver).await.expect("should return receivable"), vec![(send_id, 10)]);
+
+    db.remove_block(send_block_id).await.expect("should remove block");
+    assert!(db.get_transaction_by_id(send_id).await.is_err());
+    assert!(db.get_receivable(receiver).await.expect("should return receivable").is_empty());
+}
+
 // #[tokio::test]
 // async fn test_get_send_recipient() {
 //     let mut db = TestStorage::new().await.db;
@@ -1,39 +1,209 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::chain::{ChainError, ProtocolParams, VersionSchedule};
 use crate::storage;
-use crate::{state::ChampStateArc, storage::DatabaseError};
+use crate::{
+    state::ChampStateArc,
+    storage::{Database, DatabaseError},
+};
 
-use crypto::{self, signatures::ed25519::verify_signature};
+use crypto::{
+    self,
+    signatures::ed25519::{verify_signature, verify_signatures_batch},
+};
 use encoding::account::{generate_account_address, validate_account_address};
 use pog_proto::api::{
     transaction::{Data, TxClaim, TxSend},
-    SignedBlock, Transaction,
+    AccountID, BlockID, BlockVersion, SignedBlock, Transaction, TransactionID,
 };
 use prost::Message;
 use thiserror::Error;
 use tokio::task::JoinHandle;
 use tracing::{debug, trace};
 
-#[derive(Error, Debug)]
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
 pub enum Validation {
-    #[error("transactions could not be validated {0}")]
-    TxValidationError(String),
     #[error("invalid block height")]
     BlockHeightError,
     #[error("previous block did not match")]
     PreviousBlockError,
+    #[error("previous block not found")]
+    PreviousBlockMissing,
+    #[error("invalid block signature")]
+    InvalidSignature,
     #[error("transaction data not found")]
     TransactionDataNotFound,
     #[error("corresponding send tx not found")]
     SendTxNotFound,
+    #[error("send tx has already been claimed")]
+    SendTxAlreadyClaimed,
+    #[error("send tx is not addressed to the claiming account")]
+    ClaimReceiverMismatch,
+    #[error("send tx is claimed more than once in this block")]
+    DoubleClaim,
+    #[error("send tx is already claimed by a pending block")]
+    PendingDoubleClaim,
     #[error("attempt to claim non send tx")]
     MissmatchedTx,
     #[error("duplicate transaction")]
     DuplicatedTx,
     #[error("too many transactions")]
     TooManyTransactions,
+    #[error("block is too large")]
+    BlockTooLarge,
+    #[error("transaction payload is too large")]
+    PayloadTooLarge,
+    #[error("send amount is below the minimum")]
+    SendAmountTooLow,
+    #[error("previous block id has an invalid length")]
+    InvalidPreviousLength,
     #[error("send receiver cannot be block account")]
     ReceiverAccountError,
+    #[error("block balance does not match its transactions")]
+    BalanceMismatch,
     #[error("block already exists")]
     BlockDuplicate,
+    #[error("unknown block version")]
+    UnknownVersion,
+    #[error("block version is not active at the time of the account's previous block")]
+    InactiveVersion,
+    #[error("another block exists at the same height")]
+    ConflictingBlock,
+    #[error("representative is not a valid account")]
+    InvalidRepresentative,
+    #[error("delegation would create a cycle of representatives")]
+    DelegationCycle,
+    #[error("account delegated too recently")]
+    RedelegationCooldown,
+    #[error("light nodes only accept blocks of their own accounts")]
+    UntrackedAccount,
+    #[error("block timestamp is too far in the future")]
+    FutureTimestamp,
+}
+
+/// How final a validation failure is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The block depends on data this node doesn't have yet and might become valid later
+    MissingDependency,
+    /// The block can never become valid
+    Invalid,
+}
+
+impl Validation {
+    /// Stable identifier of the violated rule. These are part of the public api and must not be renamed.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Validation::BlockHeightError => "block_height",
+            Validation::PreviousBlockError => "previous_block_mismatch",
+            Validation::PreviousBlockMissing => "previous_block_missing",
+            Validation::InvalidSignature => "invalid_signature",
+            Validation::TransactionDataNotFound => "tx_data_missing",
+            Validation::SendTxNotFound => "send_tx_missing",
+            Validation::SendTxAlreadyClaimed => "send_tx_claimed",
+            Validation::ClaimReceiverMismatch => "claim_receiver_mismatch",
+            Validation::DoubleClaim => "double_claim",
+            Validation::PendingDoubleClaim => "pending_double_claim",
+            Validation::MissmatchedTx => "claim_non_send_tx",
+            Validation::DuplicatedTx => "duplicate_tx",
+            Validation::TooManyTransactions => "too_many_txs",
+            Validation::BlockTooLarge => "block_too_large",
+            Validation::PayloadTooLarge => "payload_too_large",
+            Validation::SendAmountTooLow => "send_amount_too_low",
+            Validation::InvalidPreviousLength => "invalid_previous_length",
+            Validation::ReceiverAccountError => "invalid_receiver",
+            Validation::BalanceMismatch => "balance_mismatch",
+            Validation::BlockDuplicate => "duplicate_block",
+            Validation::UnknownVersion => "unknown_version",
+            Validation::InactiveVersion => "inactive_version",
+            Validation::ConflictingBlock => "conflicting_block",
+            Validation::InvalidRepresentative => "invalid_representative",
+            Validation::DelegationCycle => "delegation_cycle",
+            Validation::RedelegationCooldown => "redelegation_cooldown",
+            Validation::UntrackedAccount => "untracked_account",
+            Validation::FutureTimestamp => "future_timestamp",
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        match self {
+            Validation::PreviousBlockMissing | Validation::SendTxNotFound => Severity::MissingDependency,
+            // the block might still win the vote between the competing blocks
+            Validation::ConflictingBlock => Severity::MissingDependency,
+            // the block becomes valid once the node's clock caught up
+            Validation::FutureTimestamp => Severity::MissingDependency,
+            _ => Severity::Invalid,
+        }
+    }
+}
+
+/// A single violated rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub rule: Validation,
+    /// Index of the offending transaction in the block, `None` for block level rules
+    pub tx_index: Option<usize>,
+}
+
+/// Collects every rule a block violates
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    pub violations: Vec<Violation>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    /// The most severe violation, `None` if the block is valid
+    pub fn severity(&self) -> Option<Severity> {
+        self.violations.iter().map(|v| v.rule.severity()).max()
+    }
+
+    pub fn add(&mut self, rule: Validation, tx_index: Option<usize>) {
+        self.violations.push(Violation {
+            rule,
+            tx_index,
+        });
+    }
+
+    pub fn extend(&mut self, other: ValidationReport) {
+        self.violations.extend(other.violations);
+    }
+
+    /// Adds a failed rule to the report and passes node errors through,
+    /// since those mean the block could not be validated at all
+    fn record<T>(
+        &mut self,
+        result: Result<T, BlockValidationError>,
+        tx_index: Option<usize>,
+    ) -> Result<Option<T>, Node> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(BlockValidationError::Invalid(rule)) => {
+                self.add(rule, tx_index);
+                Ok(None)
+            }
+            Err(BlockValidationError::Error(err)) => Err(err),
+        }
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let violations: Vec<String> = self
+            .violations
+            .iter()
+            .map(|v| match v.tx_index {
+                Some(i) => format!("{} (tx {}): {}", v.rule.code(), i, v.rule),
+                None => format!("{}: {}", v.rule.code(), v.rule),
+            })
+            .collect();
+        write!(f, "{}", violations.join(", "))
+    }
 }
 
 #[derive(Error, Debug)]
@@ -55,6 +225,8 @@ pub enum Node {
     AsyncError,
     #[error{"block id could not be created"}]
     BlockIdError,
+    #[error{"chain error: {0}"}]
+    ChainError(ChainError),
 }
 
 #[derive(Error, Debug)]
@@ -65,112 +237,475 @@ pub enum BlockValidationError {
     Error(#[from] Node),
 }
 
-// Validate block
-#[allow(dead_code)]
+/// Validates a block against the current state of the database
+///
+/// Every violated rule is collected in the returned report. An error is only returned
+/// if the node was unable to validate the block, e.g. because the database failed.
+pub async fn validate(block: &SignedBlock, state: &ChampStateArc) -> Result<ValidationReport, Node> {
+    let data = block.data.as_ref().ok_or(Node::BlockDataNotFound)?;
+    let signature_valid = verify_signature(&data.encode_to_vec(), &block.public_key, &block.signature).is_ok();
+    validate_with_signature(block, state, signature_valid).await
+}
+
+/// Verifies the signatures of many blocks in a single batch on the blocking thread pool
+///
+/// Signatures don't depend on any state, so this can run before the stateful checks
+/// in [`validate_with_signature`]. Returns whether each signature is valid.
+pub async fn verify_signatures(blocks: &[SignedBlock]) -> Result<Vec<bool>, Node> {
+    let messages: Vec<(Vec<u8>, Vec<u8>, Vec<u8>)> = blocks
+        .iter()
+        .map(|b| {
+            let data = b.data.as_ref().map(|d| d.encode_to_vec()).unwrap_or_default();
+            (data, b.public_key.clone(), b.signature.clone())
+        })
+        .collect();
+
+    tokio::task::spawn_blocking(move || {
+        let items: Vec<(&[u8], &[u8], &[u8])> =
+            messages.iter().map(|(d, k, s)| (d.as_slice(), k.as_slice(), s.as_slice())).collect();
+        verify_signatures_batch(&items).iter().map(|r| r.is_ok()).collect()
+    })
+    .await
+    .map_err(|_| Node::AsyncError)
+}
+
+/// Validates a block whose signature has already been verified, see [`verify_signatures`]
 #[tracing::instrument]
-pub async fn validate(block: &SignedBlock, state: &ChampStateArc) -> Result<(), BlockValidationError> {
+pub async fn validate_with_signature(
+    block: &SignedBlock,
+    state: &ChampStateArc,
+    signature_valid: bool,
+) -> Result<ValidationReport, Node> {
     debug!("validating a block");
+    let mut report = ValidationReport::default();
 
-    let data = block.clone().data.ok_or(Node::BlockDataNotFound)?;
-    let public_key = &block.public_key;
-    let signature = &block.signature;
-    let db = &state.db.lock().await;
-    let account_id = generate_account_address(public_key.to_vec()).map_err(|_| Node::CryptoError)?;
+    let (params, schedule) = {
+        let consensus = &state.config.read().await.consensus;
+        (
+            consensus.protocol_params().map_err(Node::ChainError)?,
+            consensus.version_schedule().map_err(Node::ChainError)?,
+        )
+    };
 
-    let response = db.get_latest_block_by_account(account_id).await;
+    // signature
+    if !signature_valid {
+        report.add(Validation::InvalidSignature, None);
+    }
 
-    let latest_block = match response {
-        Ok(block) => block,
-        Err(storage::DatabaseError::NoLastBlock) => return verify_account_genesis_block(block),
-        _ => return Err(Node::BlockNotFound.into()),
-    };
+    // timestamp
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    report.record(verify_timestamp(block, &params, now), None)?;
 
-    let id = block.get_id().map_err(|_| Node::BlockIdError)?;
-    match db.get_block_by_id(id).await {
-        Ok(_) => return Err(Validation::BlockDuplicate.into()),
-        Err(storage::DatabaseError::BlockNotFound) => (),
-        Err(e) => return Err(Node::DBError(e).into()),
+    // the rules a block has to follow depend on its version
+    match get_version_timestamp(block, state).await? {
+        Some(timestamp) => {
+            if let Some(version) = report.record(verify_version(block, &schedule, timestamp), None)? {
+                match version {
+                    BlockVersion::V1 => report.extend(validate_v1(block, state, &params).await?),
+                }
+            }
+        }
+        // the version can only be checked once the previous block is committed
+        None => report.add(Validation::PreviousBlockMissing, None),
     }
 
-    // signature
-    verify_signature(&data.encode_to_vec(), public_key, signature).map_err(|_| Node::CryptoError)?;
-    // height / previous block
-    verify_previous_block(block, &latest_block)?;
-    // transactions / balance
-    verify_transactions(block, &latest_block, state).await?;
+    if report.is_valid() {
+        trace!("Block successfully validated. Block={:?}", block);
+    } else {
+        debug!("block failed validation: {}", report);
+    }
 
-    trace!("Block successfully validated. Block={:?}", block);
+    Ok(report)
+}
 
+/// Verifies that the block timestamp is at most `max_clock_skew` seconds ahead of `now`
+fn verify_timestamp(block: &SignedBlock, params: &ProtocolParams, now: u64) -> Result<(), BlockValidationError> {
+    if block.timestamp > now.saturating_add(params.max_clock_skew) {
+        return Err(Validation::FutureTimestamp.into());
+    }
     Ok(())
 }
 
+/// Returns the timestamp the version of a block is selected by, `None` if its previous block isn't committed yet
+///
+/// The timestamp of a block is chosen by its creator, so the timestamp of the committed previous block is used instead.
+/// The first block of an account has no previous block and uses its own timestamp, which can't be
+/// further in the future than the allowed clock skew, see [`verify_timestamp`].
+async fn get_version_timestamp(block: &SignedBlock, state: &ChampStateArc) -> Result<Option<u64>, Node> {
+    let data = block.data.as_ref().ok_or(Node::BlockDataNotFound)?;
+    if data.height == 0 {
+        return Ok(Some(block.timestamp));
+    }
+
+    let account_id = generate_account_address(block.public_key.to_vec()).map_err(|_| Node::CryptoError)?;
+    let db = state.db.lock().await;
+    let previous = db.get_block_by_height(account_id, &(data.height - 1)).await.map_err(Node::DBError)?;
+    Ok(previous.map(|previous| previous.timestamp))
+}
+
+/// Verifies that the block version is known and active at `timestamp`, see [`get_version_timestamp`]
+fn verify_version(
+    block: &SignedBlock,
+    schedule: &VersionSchedule,
+    timestamp: u64,
+) -> Result<BlockVersion, BlockValidationError> {
+    let data = block.data.as_ref().ok_or(Node::BlockDataNotFound)?;
+    let version = BlockVersion::from_i32(data.version).ok_or(Validation::UnknownVersion)?;
+
+    if schedule.active_version(timestamp) != Some(version) {
+        return Err(Validation::InactiveVersion.into());
+    }
+
+    Ok(version)
+}
+
+/// Validates a block with the rules of version 1
+async fn validate_v1(
+    block: &SignedBlock,
+    state: &ChampStateArc,
+    params: &ProtocolParams,
+) -> Result<ValidationReport, Node> {
+    let mut report = ValidationReport::default();
+
+    let account_id = generate_account_address(block.public_key.to_vec()).map_err(|_| Node::CryptoError)?;
+    let id = block.get_id().map_err(|_| Node::BlockIdError)?;
+
+    report.extend(verify_protocol_limits(block, params)?);
+    report.extend(verify_delegations(block, state, params).await?);
+
+    let latest_block = {
+        let db = state.db.lock().await;
+
+        match db.get_block_by_id(id).await {
+            Ok(_) => report.add(Validation::BlockDuplicate, None),
+            Err(storage::DatabaseError::BlockNotFound) => (),
+            Err(e) => return Err(Node::DBError(e)),
+        }
+
+        match db.get_latest_block_by_account(account_id).await {
+            Ok(block) => Some(block),
+            Err(storage::DatabaseError::NoLastBlock) => None,
+            Err(e) => return Err(Node::DBError(e)),
+        }
+    };
+
+    let latest_block = match latest_block {
+        Some(block) => block,
+        None => {
+            report.record(verify_account_genesis_block(block), None)?;
+            return Ok(report);
+        }
+    };
+
+    // height / previous block
+    report.record(verify_previous_block(block, &latest_block), None)?;
+    // transactions / balance
+    report.extend(verify_transactions(block, &latest_block, state).await?);
+
+    Ok(report)
+}
+
 // TODO: add error handling so validation error go to voting
 // Verifies the transactions and balances
 async fn verify_transactions(
     new_block: &SignedBlock,
     prev_block: &SignedBlock,
     state: &ChampStateArc,
-) -> Result<(), BlockValidationError> {
+) -> Result<ValidationReport, Node> {
     debug!("verify transactions");
     // go through all tx in the block and do math to see new balance
     // check against block balance
+    let mut report = ValidationReport::default();
     let new_data = new_block.data.clone().ok_or(Node::BlockDataNotFound)?;
     let prev_data = prev_block.data.clone().ok_or(Node::BlockDataNotFound)?;
 
     let mut transaction_ids: Vec<[u8; 32]> = vec![];
+    let mut claimed_sends: Vec<Vec<u8>> = vec![];
+    // the balance can only be checked if the amount of every transaction is known
+    let mut balance_known = true;
 
-    if new_data.transactions.len() > 255 {
-        return Err(Validation::TooManyTransactions.into());
-    }
+    let blockid = new_block.get_id().map_err(|_| Node::BlockIdError)?;
+    let mut tokio_tasks: Vec<(usize, JoinHandle<Result<i128, BlockValidationError>>)> = vec![];
 
-    let mut new_balance: i128 = prev_data.balance as i128;
-    let mut tokio_tasks: Vec<JoinHandle<Result<i128, BlockValidationError>>> = vec![];
+    for (index, transaction) in new_data.transactions.iter().enumerate() {
+        // validate that a send is only claimed once
+        if let Some(Data::TxClaim(claim)) = &transaction.data {
+            if claimed_sends.contains(&claim.send_transaction_id) {
+                report.add(Validation::DoubleClaim, Some(index));
+                balance_known = false;
+                continue;
+            }
+            claimed_sends.push(claim.send_transaction_id.clone());
+        }
 
-    for transaction in &new_data.transactions {
         // validate that transaction is not duplicated
-        let blockid = new_block.get_id().map_err(|_| Node::BlockNotFound)?;
         let txid = transaction.get_id(blockid).map_err(|_| Node::TxNotFound)?;
         if transaction_ids.contains(&txid) {
-            return Err(Validation::DuplicatedTx.into());
+            report.add(Validation::DuplicatedTx, Some(index));
+            balance_known = false;
+            continue;
         }
         transaction_ids.push(txid);
 
         let s = state.clone();
         let tx = transaction.clone();
         let block = new_block.clone();
-        let balance = new_balance;
         // concurrent verification
         let task: JoinHandle<Result<i128, BlockValidationError>> =
-            tokio::spawn(async move { tx_verification(&s, block, &balance, &tx).await });
-        tokio_tasks.push(task);
+            tokio::spawn(async move { tx_verification(&s, block, &tx).await });
+        tokio_tasks.push((index, task));
     }
 
-    for t in tokio_tasks {
-        new_balance += t.await.map_err(|_| Node::AsyncError)??;
+    // light nodes don't store the blocks of other accounts, so they usually miss the sends their own accounts claim
+    let skip_missing_sends = !state.mode.capabilities().all_accounts;
+
+    let mut new_balance: i128 = prev_data.balance as i128;
+    for (index, t) in tokio_tasks {
+        let result = t.await.map_err(|_| Node::AsyncError)?;
+        if skip_missing_sends && matches!(result, Err(BlockValidationError::Invalid(Validation::SendTxNotFound))) {
+            balance_known = false;
+            continue;
+        }
+        match report.record(result, Some(index))? {
+            Some(amount) => new_balance += amount,
+            None => balance_known = false,
+        }
     }
 
-    if new_balance == new_data.balance as i128 && new_balance >= 0 {
-        return Ok(());
+    if balance_known && new_balance != new_data.balance as i128 {
+        report.add(Validation::BalanceMismatch, None);
     }
 
-    Err(Validation::TxValidationError("verify transactions".to_string()).into())
+    Ok(report)
+}
+
+/// Verifies that delegations don't create a cycle of representatives and respect the redelegation cooldown
+///
+/// Only committed delegations are considered, pending blocks of other accounts are not.
+async fn verify_delegations(
+    block: &SignedBlock,
+    state: &ChampStateArc,
+    params: &ProtocolParams,
+) -> Result<ValidationReport, Node> {
+    let mut report = ValidationReport::default();
+    let data = block.data.as_ref().ok_or(Node::BlockDataNotFound)?;
+    let delegations: Vec<(usize, &Vec<u8>)> = data
+        .transactions
+        .iter()
+        .enumerate()
+        .filter_map(|(index, tx)| match &tx.data {
+            Some(Data::TxDelegate(delegate)) => Some((index, &delegate.representative)),
+            _ => None,
+        })
+        .collect();
+    if delegations.is_empty() {
+        return Ok(report);
+    }
+
+    debug!("verify delegations");
+    let account_id = generate_account_address(block.public_key.to_vec()).map_err(|_| Node::CryptoError)?;
+    let db = state.db.lock().await;
+
+    let mut last_delegation =
+        get_last_delegation(&**db, account_id, block, params.redelegation_cooldown).await.map_err(Node::DBError)?;
+    for (index, representative) in delegations {
+        if let Some(timestamp) = last_delegation {
+            if block.timestamp < timestamp.saturating_add(params.redelegation_cooldown) {
+                report.add(Validation::RedelegationCooldown, Some(index));
+            }
+        }
+        last_delegation = Some(block.timestamp);
+
+        let valid = validate_account_address(representative.clone()).is_ok();
+        let representative: AccountID = match representative.clone().try_into() {
+            Ok(representative) if valid => representative,
+            _ => {
+                report.add(Validation::InvalidRepresentative, Some(index));
+                continue;
+            }
+        };
+        if creates_delegation_cycle(&**db, account_id, representative).await.map_err(Node::DBError)? {
+            report.add(Validation::DelegationCycle, Some(index));
+        }
+    }
+
+    Ok(report)
+}
+
+/// Finds the timestamp of an account's latest delegation before a block, if it was within the cooldown
+async fn get_last_delegation(
+    db: &dyn Database,
+    account_id: AccountID,
+    block: &SignedBlock,
+    cooldown: u64,
+) -> Result<Option<u64>, DatabaseError> {
+    let height = block.data.as_ref().map(|data| data.height).unwrap_or_default();
+    for height in (0..height).rev() {
+        let older = match db.get_block_by_height(account_id, &height).await? {
+            Some(older) => older,
+            None => break,
+        };
+        if older.timestamp.saturating_add(cooldown) <= block.timestamp {
+            break;
+        }
+        let delegates = older
+            .data
+            .iter()
+            .flat_map(|data| &data.transactions)
+            .any(|tx| matches!(tx.data, Some(Data::TxDelegate(_))));
+        if delegates {
+            return Ok(Some(older.timestamp));
+        }
+    }
+    Ok(None)
+}
+
+/// Follows the representatives starting at `representative`, a cycle is created if they lead back to the account
+///
+/// Delegating to the account itself is a cycle as well.
+async fn creates_delegation_cycle(
+    db: &dyn Database,
+    account_id: AccountID,
+    representative: AccountID,
+) -> Result<bool, DatabaseError> {
+    let mut visited = HashSet::new();
+    let mut current = representative;
+    loop {
+        if current == account_id {
+            return Ok(true);
+        }
+        // already visited accounts are part of an older cycle that doesn't include this account
+        if !visited.insert(current) {
+            return Ok(false);
+        }
+        match db.get_account_delegate(current).await? {
+            Some(next) => current = next,
+            None => return Ok(false),
+        }
+    }
+}
+
+/// Verifies the size limits of the chain's protocol parameters
+fn verify_protocol_limits(block: &SignedBlock, params: &ProtocolParams) -> Result<ValidationReport, Node> {
+    debug!("verify protocol limits");
+    let mut report = ValidationReport::default();
+    let data = block.data.as_ref().ok_or(Node::BlockDataNotFound)?;
+
+    if block.encoded_len() > params.max_block_bytes {
+        report.add(Validation::BlockTooLarge, None);
+    }
+    if data.transactions.len() > params.max_transactions {
+        report.add(Validation::TooManyTransactions, None);
+    }
+    if data.previous.len() > std::mem::size_of::<BlockID>() {
+        report.add(Validation::InvalidPreviousLength, None);
+    }
+
+    for (index, transaction) in data.transactions.iter().enumerate() {
+        if let Some(Data::TxSend(tx)) = &transaction.data {
+            if tx.data.len() > params.max_payload_bytes {
+                report.add(Validation::PayloadTooLarge, Some(index));
+            }
+            if tx.amount < params.min_send_amount {
+                report.add(Validation::SendAmountTooLow, Some(index));
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Returns the ids of all send transactions a block claims together with the index of the claim
+pub fn get_claimed_sends(block: &SignedBlock) -> Vec<(usize, TransactionID)> {
+    let transactions = match &block.data {
+        Some(data) => &data.transactions,
+        None => return vec![],
+    };
+
+    transactions
+        .iter()
+        .enumerate()
+        .filter_map(|(index, tx)| match &tx.data {
+            Some(Data::TxClaim(claim)) => claim.send_transaction_id.clone().try_into().ok().map(|id| (index, id)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Verifies that a block doesn't claim a send that is already claimed by a block waiting in the blockpool
+///
+/// Blocks competing at the same height are handled as forks before this check, so it only applies to a later block
+/// of the account claiming a send again that one of its queued previous blocks already claims.
+pub fn verify_pending_claims(
+    block: &SignedBlock,
+    pending_claims: &HashMap<TransactionID, BlockID>,
+) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    for (index, send_id) in get_claimed_sends(block) {
+        if pending_claims.contains_key(&send_id) {
+            report.add(Validation::PendingDoubleClaim, Some(index));
+        }
+    }
+    report
+}
+
+/// A block or transaction a block depends on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Dependency {
+    Block(BlockID),
+    Transaction(TransactionID),
+}
+
+/// Returns the blocks and transactions a block is missing according to its validation report
+pub fn get_missing_dependencies(block: &SignedBlock, report: &ValidationReport) -> Vec<Dependency> {
+    let data = match &block.data {
+        Some(data) => data,
+        None => return vec![],
+    };
+
+    report
+        .violations
+        .iter()
+        .filter_map(|violation| match (&violation.rule, violation.tx_index) {
+            (Validation::PreviousBlockMissing, _) => data.previous.clone().try_into().ok().map(Dependency::Block),
+            (Validation::SendTxNotFound, Some(index)) => match data.transactions.get(index)?.data.as_ref()? {
+                Data::TxClaim(claim) => claim.send_transaction_id.clone().try_into().ok().map(Dependency::Transaction),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns the block and the send transactions other blocks can depend on once this block is accepted
+pub fn get_provided_dependencies(block: &SignedBlock) -> Result<Vec<Dependency>, Node> {
+    let block_id = block.get_id().map_err(|_| Node::BlockIdError)?;
+    let mut dependencies = vec![Dependency::Block(block_id)];
+
+    if let Some(data) = &block.data {
+        for tx in &data.transactions {
+            if let Some(Data::TxSend(_)) = tx.data {
+                dependencies.push(Dependency::Transaction(tx.get_id(block_id).map_err(|_| Node::TxNotFound)?));
+            }
+        }
+    }
+    Ok(dependencies)
 }
 
 async fn tx_verification(
     state: &ChampStateArc,
     new_block: SignedBlock,
-    new_balance: &i128,
     transaction: &Transaction,
 ) -> Result<i128, BlockValidationError> {
-    // calculate the new balance after all transactions are processed
+    // calculate the balance change of this transaction
     let tx_type = transaction.data.as_ref().ok_or(Validation::TransactionDataNotFound)?;
 
-    let result_balance = match tx_type {
+    let balance_change = match tx_type {
         Data::TxSend(tx) => validate_send(tx.amount, tx, new_block)?,
         Data::TxClaim(tx) => validate_collect(state, tx, &new_block).await?,
-        _ => *new_balance,
+        _ => 0,
     };
-    Ok(result_balance)
+    Ok(balance_change)
 }
 
 // Verifies the block height and previous block
@@ -179,7 +714,11 @@ fn verify_previous_block(new_block: &SignedBlock, prev_block: &SignedBlock) -> R
     let new_data = new_block.data.as_ref().ok_or(Node::BlockDataNotFound)?;
     let prev_data = prev_block.data.as_ref().ok_or(Node::BlockDataNotFound)?;
 
-    if new_data.height - 1 != prev_data.height {
+    // blocks in between are missing, this block might become valid once they arrive
+    if new_data.height > prev_data.height + 1 {
+        return Err(Validation::PreviousBlockMissing.into());
+    }
+    if new_data.height != prev_data.height + 1 {
         return Err(Validation::BlockHeightError.into());
     }
     if new_data.previous != prev_block.get_id().map_err(|_| Node::BlockNotFound)?.to_vec() {
@@ -188,12 +727,12 @@ fn verify_previous_block(new_block: &SignedBlock, prev_block: &SignedBlock) -> R
     Ok(())
 }
 
-// Verifies the block height and previous block
+// Verifies the block height of the first block of an account
 fn verify_account_genesis_block(block: &SignedBlock) -> Result<(), BlockValidationError> {
-    let data = block.data.clone().ok_or(BlockValidationError::Error(Node::BlockDataNotFound))?;
+    let data = block.data.as_ref().ok_or(Node::BlockDataNotFound)?;
 
     if data.height != 0 {
-        return Err(Validation::BlockHeightError.into());
+        return Err(Validation::PreviousBlockMissing.into());
     }
 
     Ok(())
@@ -225,13 +764,13 @@ async fn validate_collect(
     let db = &state.db.lock().await;
     let resp = db.get_send_recipient(send_id).await;
     if resp.map_err(Node::DBError)?.is_some() {
-        return Err(Validation::TxValidationError("validate collect 1".to_string()).into());
+        return Err(Validation::SendTxAlreadyClaimed.into());
     }
 
     let db_response = db.get_transaction_by_id(send_id).await;
     let receive_tx = match db_response {
         Ok(t) => t,
-        Err(_) => return Err(Validation::TxValidationError("validate collect 2".to_string()).into()),
+        Err(_) => return Err(Validation::SendTxNotFound.into()),
     };
 
     let sendtx = match &receive_tx.data {
@@ -243,7 +782,7 @@ async fn validate_collect(
     let account_id = generate_account_address(block.public_key.to_vec()).map_err(|_| Node::AccountError)?;
     // check if account is allowed to receive
     if account_id.to_vec() != sendtx.receiver {
-        return Err(Validation::TxValidationError("validate collect 3".to_string()).into());
+        return Err(Validation::ClaimReceiverMismatch.into());
     }
 
     Ok(sendtx.amount.into())
@@ -251,16 +790,23 @@ async fn validate_collect(
 
 #[cfg(test)]
 mod tests {
-    use crate::validation::block::{verify_previous_block, verify_transactions};
+    use crate::chain::{ProtocolParams, VersionActivation};
+    use crate::validation::block::{
+        validate, verify_delegations, verify_pending_claims, verify_previous_block, verify_protocol_limits,
+        verify_timestamp, verify_transactions, BlockValidationError, Severity, Validation, Violation,
+    };
     use crate::ChampState;
     use anyhow::Result;
+    use encoding::account::generate_account_address;
     use encoding::zbase32::FromZbase;
-    use pog_proto::api::transaction::TxClaim;
+    use pog_proto::api::transaction::{TxClaim, TxDelegate};
     use pog_proto::api::{
         signed_block::BlockData,
         transaction::{Data, TxSend},
-        SignedBlock, Transaction,
+        BlockVersion, SignedBlock, Transaction,
     };
+    use pog_proto::rpc::node_admin::Mode;
+    use std::collections::HashMap;
 
     #[test]
     fn test_verify_previous_block() -> Result<()> {
@@ -393,11 +939,492 @@ mod tests {
         };
         let state = ChampState::mock().await;
         state.db.lock().await.add_block(data_block_1).await.expect("block should be added");
-        verify_transactions(&block, &prev_block, &state).await.expect("should work");
-        verify_transactions(&check_claim, &check_claim_previous, &state)
+        let report = verify_transactions(&block, &prev_block, &state).await.expect("should work");
+        assert!(report.is_valid(), "{}", report);
+        let report = verify_transactions(&check_claim, &check_claim_previous, &state)
             .await
             .expect("tx should be verified. Tx Nr: 2");
+        assert!(report.is_valid(), "{}", report);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_report_collects_all_violations() -> Result<()> {
+        let prev_block = SignedBlock {
+            signature: b"thisIsNewSignature".to_vec(),
+            public_key: b"test".to_vec(),
+            timestamp: 1,
+            data: Some(BlockData {
+                version: 0,
+                signature_type: 0,
+                balance: 100,
+                height: 4,
+                previous: b"blockBeforeMe".to_vec(),
+                transactions: vec![],
+            }),
+        };
+        let send = Transaction {
+            data: Some(Data::TxSend(TxSend {
+                receiver: Vec::from_zbase("yy5xyknabqan31b8fkpyrd4nydtwpausi3kxgta").unwrap(),
+                amount: 10,
+                data: vec![],
+            })),
+        };
+        let block = SignedBlock {
+            signature: b"signedByMe".to_vec(),
+            public_key: b"test".to_vec(),
+            timestamp: 1,
+            data: Some(BlockData {
+                version: 0,
+                signature_type: 0,
+                balance: 90,
+                height: 5,
+                previous: prev_block.get_id().expect("get Block ID").to_vec(),
+                transactions: vec![
+                    send.clone(),
+                    send,
+                    Transaction {
+                        data: None,
+                    },
+                ],
+            }),
+        };
+
+        let state = ChampState::mock().await;
+        let report = verify_transactions(&block, &prev_block, &state).await.expect("should validate");
+
+        assert_eq!(
+            report.violations,
+            vec![
+                Violation {
+                    rule: Validation::DuplicatedTx,
+                    tx_index: Some(1),
+                },
+                Violation {
+                    rule: Validation::ReceiverAccountError,
+                    tx_index: Some(0),
+                },
+                Violation {
+                    rule: Validation::TransactionDataNotFound,
+                    tx_index: Some(2),
+                },
+            ]
+        );
+        assert_eq!(report.severity(), Some(Severity::Invalid));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_skipped_tx_has_no_balance_mismatch() -> Result<()> {
+        let prev_block = SignedBlock {
+            signature: b"thisIsNewSignature".to_vec(),
+            public_key: b"someKey".to_vec(),
+            timestamp: 1,
+            data: Some(BlockData {
+                version: 0,
+                signature_type: 0,
+                balance: 100,
+                height: 4,
+                previous: b"blockBeforeMe".to_vec(),
+                transactions: vec![],
+            }),
+        };
+        let send = Transaction {
+            data: Some(Data::TxSend(TxSend {
+                receiver: Vec::from_zbase("yy5xyknabqan31b8fkpyrd4nydtwpausi3kxgta").unwrap(),
+                amount: 10,
+                data: vec![],
+            })),
+        };
+        // the balance counts both sends, the duplicate is skipped by the validation
+        let block = SignedBlock {
+            signature: b"signedByMe".to_vec(),
+            public_key: b"someKey".to_vec(),
+            timestamp: 1,
+            data: Some(BlockData {
+                version: 0,
+                signature_type: 0,
+                balance: 80,
+                height: 5,
+                previous: prev_block.get_id().expect("get Block ID").to_vec(),
+                transactions: vec![send.clone(), send],
+            }),
+        };
+
+        let state = ChampState::mock().await;
+        let report = verify_transactions(&block, &prev_block, &state).await.expect("should validate");
+        assert_eq!(
+            report.violations,
+            vec![Violation {
+                rule: Validation::DuplicatedTx,
+                tx_index: Some(1),
+            }]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_double_claim_in_block() -> Result<()> {
+        let prev_block = SignedBlock {
+            signature: b"thisIsNewSignature".to_vec(),
+            public_key: b"test".to_vec(),
+            timestamp: 1,
+            data: Some(BlockData {
+                version: 0,
+                signature_type: 0,
+                balance: 0,
+                height: 4,
+                previous: b"blockBeforeMe".to_vec(),
+                transactions: vec![],
+            }),
+        };
+        let claim = Transaction {
+            data: Some(Data::TxClaim(TxClaim {
+                send_transaction_id: [1; 32].to_vec(),
+            })),
+        };
+        let block = SignedBlock {
+            signature: b"signedByMe".to_vec(),
+            public_key: b"test".to_vec(),
+            timestamp: 1,
+            data: Some(BlockData {
+                version: 0,
+                signature_type: 0,
+                balance: 20,
+                height: 5,
+                previous: prev_block.get_id().expect("get Block ID").to_vec(),
+                transactions: vec![claim.clone(), claim],
+            }),
+        };
+
+        let state = ChampState::mock().await;
+        let report = verify_transactions(&block, &prev_block, &state).await.expect("should validate");
+        assert!(report.violations.contains(&Violation {
+            rule: Validation::DoubleClaim,
+            tx_index: Some(1),
+        }));
+
+        let mut pending_claims = HashMap::new();
+        pending_claims.insert([1; 32], [2; 32]);
+        let report = verify_pending_claims(&block, &pending_claims);
+        assert_eq!(
+            report.violations,
+            vec![Violation {
+                rule: Validation::PendingDoubleClaim,
+                tx_index: Some(0),
+            }]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_light_claim() -> Result<()> {
+        let prev_block = SignedBlock {
+            signature: b"thisIsNewSignature".to_vec(),
+            public_key: b"test".to_vec(),
+            timestamp: 1,
+            data: Some(BlockData {
+                version: 0,
+                signature_type: 0,
+                balance: 0,
+                height: 4,
+                previous: b"blockBeforeMe".to_vec(),
+                transactions: vec![],
+            }),
+        };
+        // claims a send of an account the node doesn't store
+        let block = SignedBlock {
+            signature: b"signedByMe".to_vec(),
+            public_key: b"test".to_vec(),
+            timestamp: 1,
+            data: Some(BlockData {
+                version: 0,
+                signature_type: 0,
+                balance: 20,
+                height: 5,
+                previous: prev_block.get_id().expect("get Block ID").to_vec(),
+                transactions: vec![Transaction {
+                    data: Some(Data::TxClaim(TxClaim {
+                        send_transaction_id: [1; 32].to_vec(),
+                    })),
+                }],
+            }),
+        };
+
+        let state = ChampState::mock().await;
+        let report = verify_transactions(&block, &prev_block, &state).await.expect("should validate");
+        assert_eq!(
+            report.violations,
+            vec![Violation {
+                rule: Validation::SendTxNotFound,
+                tx_index: Some(0),
+            }]
+        );
+
+        // light nodes accept the claim without knowing its amount, so the balance isn't checked either
+        state.mode.set(Mode::Light);
+        let report = verify_transactions(&block, &prev_block, &state).await.expect("should validate");
+        assert!(report.is_valid(), "{}", report);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_verify_delegations() -> Result<()> {
+        let delegating_block =
+            |public_key: &[u8], height: u64, timestamp: u64, representatives: &[Vec<u8>]| SignedBlock {
+                signature: b"signedByMe".to_vec(),
+                public_key: public_key.to_vec(),
+                timestamp,
+                data: Some(BlockData {
+                    version: 0,
+                    signature_type: 0,
+                    balance: 0,
+                    height,
+                    previous: vec![],
+                    transactions: representatives
+                        .iter()
+                        .map(|representative| Transaction {
+                            data: Some(Data::TxDelegate(TxDelegate {
+                                representative: representative.clone(),
+                            })),
+                        })
+                        .collect(),
+                }),
+            };
+        let address = |public_key: &[u8]| generate_account_address(public_key.to_vec()).unwrap().to_vec();
+        let codes = |report: crate::validation::block::ValidationReport| -> Vec<(&str, Option<usize>)> {
+            report.violations.iter().map(|v| (v.rule.code(), v.tx_index)).collect()
+        };
+
+        let state = ChampState::mock().await;
+        let params = ProtocolParams {
+            redelegation_cooldown: 100,
+            ..ProtocolParams::for_chain("dev")?
+        };
+        // a -> b -> c
+        state.db.lock().await.add_block(delegating_block(b"a", 0, 0, &[address(b"b")])).await?;
+        state.db.lock().await.add_block(delegating_block(b"b", 0, 0, &[address(b"c")])).await?;
+
+        let block = delegating_block(b"c", 0, 0, &[address(b"a"), address(b"c"), vec![1; 24]]);
+        assert_eq!(
+            codes(verify_delegations(&block, &state, &params).await?),
+            vec![
+                ("delegation_cycle", Some(0)),
+                ("redelegation_cooldown", Some(1)),
+                ("delegation_cycle", Some(1)),
+                ("redelegation_cooldown", Some(2)),
+                ("invalid_representative", Some(2)),
+            ]
+        );
+
+        // a can redelegate once the cooldown is over
+        let block = delegating_block(b"a", 1, 99, &[address(b"c")]);
+        assert_eq!(
+            codes(verify_delegations(&block, &state, &params).await?),
+            vec![("redelegation_cooldown", Some(0))]
+        );
+        let block = delegating_block(b"a", 1, 100, &[address(b"c")]);
+        assert!(verify_delegations(&block, &state, &params).await?.is_valid());
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_protocol_limits() -> Result<()> {
+        let params = ProtocolParams {
+            max_transactions: 2,
+            max_payload_bytes: 4,
+            max_block_bytes: 1024,
+            min_send_amount: 5,
+            redelegation_cooldown: 0,
+            max_clock_skew: 0,
+        };
+        let send = |amount: u64, data: &[u8]| Transaction {
+            data: Some(Data::TxSend(TxSend {
+                receiver: Vec::from_zbase("yy5xyknabqan31b8fkpyrd4nydtwpausi3kxgta").unwrap(),
+                amount,
+                data: data.to_vec(),
+            })),
+        };
+        let block = SignedBlock {
+            signature: b"signedByMe".to_vec(),
+            public_key: b"someKey".to_vec(),
+            timestamp: 1,
+            data: Some(BlockData {
+                version: 0,
+                signature_type: 0,
+                balance: 0,
+                height: 5,
+                previous: [0; 33].to_vec(),
+                transactions: vec![send(5, b"data"), send(4, b""), send(10, b"too long")],
+            }),
+        };
+
+        let codes: Vec<(&str, Option<usize>)> = verify_protocol_limits(&block, &params)
+            .expect("should verify limits")
+            .violations
+            .iter()
+            .map(|v| (v.rule.code(), v.tx_index))
+            .collect();
+        assert_eq!(
+            codes,
+            vec![
+                ("too_many_txs", None),
+                ("invalid_previous_length", None),
+                ("send_amount_too_low", Some(1)),
+                ("payload_too_large", Some(2)),
+            ]
+        );
+
+        let params = ProtocolParams {
+            max_block_bytes: 10,
+            ..params
+        };
+        let report = verify_protocol_limits(&block, &params).expect("should verify limits");
+        assert_eq!(report.violations[0].rule, Validation::BlockTooLarge);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_validate_unknown_version() -> Result<()> {
+        let block = SignedBlock {
+            signature: b"signedByMe".to_vec(),
+            public_key: b"someKey".to_vec(),
+            timestamp: 1,
+            data: Some(BlockData {
+                version: 99,
+                signature_type: 0,
+                balance: 0,
+                height: 0,
+                previous: vec![],
+                transactions: vec![],
+            }),
+        };
+
+        let state = ChampState::mock().await;
+        let report = validate(&block, &state).await.expect("should validate");
+        assert_eq!(
+            report.violations,
+            vec![
+                Violation {
+                    rule: Validation::InvalidSignature,
+                    tx_index: None,
+                },
+                Violation {
+                    rule: Validation::UnknownVersion,
+                    tx_index: None,
+                }
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_version_of_previous_block() -> Result<()> {
+        let block_data = |height: u64, previous: Vec<u8>| BlockData {
+            version: BlockVersion::V1.into(),
+            signature_type: 0,
+            balance: 0,
+            height,
+            previous,
+            transactions: vec![],
+        };
+        let previous = SignedBlock {
+            signature: b"signedByMe".to_vec(),
+            public_key: b"someKey".to_vec(),
+            timestamp: 50,
+            data: Some(block_data(0, vec![])),
+        };
+        let block = SignedBlock {
+            signature: b"signedByMe".to_vec(),
+            public_key: b"someKey".to_vec(),
+            timestamp: 200,
+            data: Some(block_data(1, previous.get_id().expect("get block ID").to_vec())),
+        };
+
+        let state = ChampState::mock().await;
+        state.config.write().await.consensus.versions = Some(vec![VersionActivation {
+            version: BlockVersion::V1,
+            activation_timestamp: 100,
+        }]);
+        state.db.lock().await.add_block(previous).await.expect("block should be added");
+
+        // the block claims to be created after the activation, but its previous block was created before it
+        let report = validate(&block, &state).await.expect("should validate");
+        assert!(report.violations.iter().any(|v| v.rule == Validation::InactiveVersion), "{}", report);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_version_of_first_block() -> Result<()> {
+        let first_block = |timestamp: u64| SignedBlock {
+            signature: b"signedByMe".to_vec(),
+            public_key: b"someKey".to_vec(),
+            timestamp,
+            data: Some(BlockData {
+                version: BlockVersion::V1.into(),
+                signature_type: 0,
+                balance: 0,
+                height: 0,
+                previous: vec![],
+                transactions: vec![],
+            }),
+        };
+
+        let state = ChampState::mock().await;
+        state.config.write().await.consensus.versions = Some(vec![VersionActivation {
+            version: BlockVersion::V1,
+            activation_timestamp: 100,
+        }]);
+
+        // the first block is checked against its own timestamp, not the time it is validated at
+        let report = validate(&first_block(50), &state).await.expect("should validate");
+        assert!(report.violations.iter().any(|v| v.rule == Validation::InactiveVersion), "{}", report);
+        let report = validate(&first_block(150), &state).await.expect("should validate");
+        assert!(!report.violations.iter().any(|v| v.rule == Validation::InactiveVersion), "{}", report);
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_timestamp() {
+        let params = ProtocolParams {
+            max_clock_skew: 10,
+            ..ProtocolParams::for_chain("dev").expect("dev chain exists")
+        };
+        let block = |timestamp: u64| SignedBlock {
+            timestamp,
+            ..Default::default()
+        };
+
+        assert!(verify_timestamp(&block(110), &params, 100).is_ok());
+        assert!(matches!(
+            verify_timestamp(&block(111), &params, 100),
+            Err(BlockValidationError::Invalid(Validation::FutureTimestamp))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_validate_missing_dependency() -> Result<()> {
+        let block = SignedBlock {
+            signature: b"signedByMe".to_vec(),
+            public_key: b"someKey".to_vec(),
+            timestamp: 1,
+            data: Some(BlockData {
+                version: 0,
+                signature_type: 0,
+                balance: 0,
+                height: 3,
+                previous: b"blockBeforeMe".to_vec(),
+                transactions: vec![],
+            }),
+        };
+
+        let state = ChampState::mock().await;
+        let report = validate(&block, &state).await.expect("should validate");
 
+        let codes: Vec<&str> = report.violations.iter().map(|v| v.rule.code()).collect();
+        assert_eq!(codes, vec!["invalid_signature", "previous_block_missing"]);
+        assert_eq!(report.severity(), Some(Severity::Invalid));
         Ok(())
     }
 }